@@ -1,13 +1,16 @@
 use crate::Extract::*;
 use clap::{App, Arg};
-use csv::{ReaderBuilder, StringRecord};
+use csv::{ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use regex::Regex;
 use std::{
     error::Error,
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, BufWriter, Write},
     num::NonZeroUsize,
     ops::Range,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 type PositionList = Vec<Range<usize>>;
@@ -15,53 +18,450 @@ type PositionList = Vec<Range<usize>>;
 #[derive(Debug)]
 pub enum Extract {
     Fields(PositionList),
+    Names {
+        names: Vec<String>,
+        complement: bool,
+    },
     Bytes(PositionList),
     Chars(PositionList),
+    // layout は各列の文字の範囲、fields は選択した列
+    FixedWidth {
+        layout: PositionList,
+        fields: PositionList,
+    },
+}
+
+#[derive(Debug)]
+pub enum Delimiter {
+    Byte(u8),
+    Str(String),
+    Regex(Regex),
+    Whitespace,
+}
+
+impl Delimiter {
+    // CSV として読む場合は、ここでは分割しない。record は行ごとに使い回す
+    fn split(&self, line: &str, record: &mut StringRecord) {
+        record.clear();
+        let mut push = |field| record.push_field(field);
+        match self {
+            Delimiter::Byte(b) => line.split(char::from(*b)).for_each(&mut push),
+            Delimiter::Str(s) => line.split(s.as_str()).for_each(&mut push),
+            Delimiter::Regex(re) => re.split(line).for_each(&mut push),
+            // awk と同様に、前後の空白は区切りとみなさない
+            Delimiter::Whitespace => line.split_whitespace().for_each(&mut push),
+        }
+    }
+}
+
+// --chars で数える単位
+#[derive(Debug, PartialEq, Eq)]
+pub enum CharUnit {
+    Scalar,
+    Grapheme,
+    Column,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Format {
+    Default,
+    Csv,
+    Tsv,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mode {
+    Literal,
+    Csv {
+        quote: u8,
+        escape: Option<u8>,
+        comment: Option<u8>,
+    },
+}
+
+// CSV として出力する場合は、区切り文字や引用符を含むフィールドを引用符で囲む
+enum Output {
+    Joined(String, BufWriter<io::Stdout>),
+    Csv(Box<Writer<io::Stdout>>),
+    // names にないフィールドのキーは f1, f2, ... とする
+    Json {
+        names: Vec<String>,
+        infer_types: bool,
+        out: BufWriter<io::Stdout>,
+    },
+}
+
+impl Output {
+    fn write<'a>(&mut self, fields: impl Iterator<Item = &'a str> + Clone) -> MyResult<()> {
+        match self {
+            Output::Joined(delimiter, out) => {
+                for (i, field) in fields.enumerate() {
+                    if i > 0 {
+                        out.write_all(delimiter.as_bytes())?;
+                    }
+                    out.write_all(field.as_bytes())?;
+                }
+                out.write_all(b"\n")?;
+            }
+            Output::Csv(writer) => {
+                // csv は空のレコードを "" と書き出すため、空行はそのまま出力する
+                let mut peek = fields.clone();
+                if matches!((peek.next(), peek.next()), (None, _) | (Some(""), None)) {
+                    writer.flush()?;
+                    io::stdout().write_all(b"\n")?;
+                } else {
+                    writer.write_record(fields)?;
+                }
+            }
+            Output::Json {
+                names,
+                infer_types,
+                out,
+            } => {
+                out.write_all(b"{")?;
+                for (i, field) in fields.enumerate() {
+                    if i > 0 {
+                        out.write_all(b",")?;
+                    }
+                    match names.get(i) {
+                        Some(name) => serde_json::to_writer(&mut *out, name)?,
+                        None => write!(out, "\"f{}\"", i + 1)?,
+                    }
+                    out.write_all(b":")?;
+                    if *infer_types && is_json_literal(field) {
+                        out.write_all(field.as_bytes())?;
+                    } else {
+                        serde_json::to_writer(&mut *out, field)?;
+                    }
+                }
+                out.write_all(b"}\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MyResult<()> {
+        match self {
+            Output::Joined(_, out) | Output::Json { out, .. } => out.flush()?,
+            Output::Csv(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+// 範囲の境界を昇順に並べておき、行を一度走査するだけで各範囲の位置を求める
+#[derive(Debug)]
+struct Plan {
+    bounds: Vec<usize>,
+    // 出力する順に、範囲の先頭と末尾が bounds の何番目にあたるか
+    ranges: Vec<(usize, usize)>,
+    // 各境界のバイト位置。行ごとに使い回す
+    offsets: Vec<usize>,
+    // 列単位では、範囲の末尾として使うバイト位置を別に求める
+    ends: Vec<usize>,
+}
+
+impl Plan {
+    fn new(pos: &[Range<usize>]) -> Plan {
+        let mut bounds: Vec<usize> = pos.iter().flat_map(|r| [r.start, r.end]).collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let index = |n| bounds.binary_search(&n).unwrap();
+        let ranges = pos.iter().map(|r| (index(r.start), index(r.end))).collect();
+        Plan {
+            bounds,
+            ranges,
+            offsets: vec![],
+            ends: vec![],
+        }
+    }
+
+    // starts は各単位の先頭のバイト位置。行末より後ろの境界は行末とする
+    fn select<'a>(
+        &'a mut self,
+        line: &'a str,
+        starts: impl Iterator<Item = usize>,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.offsets.clear();
+        let mut starts = starts.enumerate();
+        for &bound in &self.bounds {
+            let offset = starts.find(|(i, _)| *i == bound);
+            self.offsets.push(offset.map_or(line.len(), |(_, o)| o));
+        }
+        let offsets = &self.offsets;
+        self.ranges
+            .iter()
+            .map(move |&(start, end)| &line[offsets[start]..offsets[end]])
+    }
+
+    // spans は各文字の列の範囲と先頭のバイト位置。範囲の先頭は列の先頭が境界以降の最初の文字、
+    // 末尾は列の末尾が境界を越える最初の文字とし、境界をまたぐ文字を含めない
+    fn select_spans<'a>(
+        &'a mut self,
+        line: &'a str,
+        spans: impl Iterator<Item = (Range<usize>, usize)>,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.offsets.clear();
+        self.ends.clear();
+        for (span, offset) in spans {
+            while self.offsets.len() < self.bounds.len()
+                && self.bounds[self.offsets.len()] <= span.start
+            {
+                self.offsets.push(offset);
+            }
+            while self.ends.len() < self.bounds.len() && self.bounds[self.ends.len()] < span.end {
+                self.ends.push(offset);
+            }
+        }
+        self.offsets.resize(self.bounds.len(), line.len());
+        self.ends.resize(self.bounds.len(), line.len());
+        let (offsets, ends) = (&self.offsets, &self.ends);
+        self.ranges.iter().map(move |&(start, end)| {
+            let start = offsets[start];
+            &line[start..ends[end].max(start)]
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct Config {
     files: Vec<String>,
-    delimiter: u8,
+    delimiter: Delimiter,
+    mode: Mode,
+    format: Format,
+    output_delimiter: String,
+    only_delimited: bool,
+    trim: bool,
+    header: bool,
+    hide_header: bool,
+    no_split: bool,
+    char_unit: CharUnit,
+    names: Option<Vec<String>>,
+    infer_types: bool,
     extract: Extract,
 }
 
 pub fn run(config: Config) -> MyResult<()> {
+    // 出力先のパイプが閉じられた場合は、cut と同様に何も表示せずに終了する
+    match cut(&config) {
+        Err(e) if is_broken_pipe(e.as_ref()) => Ok(()),
+        result => result,
+    }
+}
+
+fn cut(config: &Config) -> MyResult<()> {
+    let mut output = config.output();
+    let mut stdout = BufWriter::new(io::stdout());
     for filename in &config.files {
         match open(filename) {
             Err(err) => eprintln!("{}: {}", filename, err),
-            Ok(reader) => {
-                match &config.extract {
-                    Chars(pos) => {
-                        reader.lines().for_each(|line| {
-                            let line = line.unwrap();
-                            let result = extract_chars(&line, pos);
-                            println!("{}", result);
-                        });
+            Ok(mut reader) => match &config.extract {
+                Chars(pos) => {
+                    let mut plan = Plan::new(pos);
+                    let mut line = String::new();
+                    while read_line(&mut reader, &mut line)? {
+                        if config.char_unit == CharUnit::Column {
+                            for value in extract_columns(&line, &mut plan) {
+                                stdout.write_all(value.as_bytes())?;
+                            }
+                        } else if config.char_unit == CharUnit::Grapheme {
+                            for value in extract_graphemes(&line, &mut plan) {
+                                stdout.write_all(value.as_bytes())?;
+                            }
+                        } else {
+                            for value in extract_chars(&line, &mut plan) {
+                                stdout.write_all(value.as_bytes())?;
+                            }
+                        }
+                        stdout.write_all(b"\n")?;
                     }
-                    Bytes(pos) => {
-                        reader.lines().for_each(|line| {
-                            let line = line.unwrap();
-                            let result = extract_bytes(&line, pos);
-                            println!("{}", result);
-                        });
+                }
+                Bytes(pos) => {
+                    // UTF-8 とは限らないため、バイト列のまま読み書きする
+                    let mut line = vec![];
+                    loop {
+                        line.clear();
+                        if reader.read_until(b'\n', &mut line)? == 0 {
+                            break;
+                        }
+                        if line.last() == Some(&b'\n') {
+                            line.pop();
+                        }
+                        let boundaries;
+                        let pos = if config.no_split {
+                            boundaries = char_boundaries(&line, pos);
+                            &boundaries
+                        } else {
+                            pos
+                        };
+                        for value in extract_bytes(&line, pos) {
+                            stdout.write_all(value)?;
+                        }
+                        stdout.write_all(b"\n")?;
                     }
-                    Fields(pos) => {
-                        let delimiter_str = String::from_utf8_lossy(&[config.delimiter.clone()]).to_string();
+                }
+                Fields(_) | Names { .. } => match (&config.mode, &config.delimiter) {
+                    (
+                        Mode::Csv {
+                            quote,
+                            escape,
+                            comment,
+                        },
+                        Delimiter::Byte(delimiter),
+                    ) => {
                         let mut reader = ReaderBuilder::new()
                             .has_headers(false)
-                            .delimiter(config.delimiter)
+                            .flexible(true)
+                            .delimiter(*delimiter)
+                            .quote(*quote)
+                            .escape(*escape)
+                            .comment(*comment)
                             .from_reader(reader);
-                        reader.records().for_each(|line| {
-                            let line = line.unwrap();
-                            let result = extract_fields(&line, pos);
-                            println!("{}", result.join(&delimiter_str));
-                        });
+                        let read_record =
+                            |record: &mut StringRecord| Ok(reader.read_record(record)?);
+                        print_records(config, filename, read_record, &mut output)?;
+                    }
+                    (_, delimiter) => {
+                        let mut line = String::new();
+                        let read_record = |record: &mut StringRecord| {
+                            let read = read_line(&mut reader, &mut line)?;
+                            delimiter.split(&line, record);
+                            Ok(read)
+                        };
+                        print_records(config, filename, read_record, &mut output)?;
                     }
+                },
+                FixedWidth { layout, .. } => {
+                    let mut plan = Plan::new(layout);
+                    let mut line = String::new();
+                    let read_record = |record: &mut StringRecord| {
+                        let read = read_line(&mut reader, &mut line)?;
+                        record.clear();
+                        for value in extract_chars(&line, &mut plan) {
+                            record.push_field(if config.trim { value.trim() } else { value });
+                        }
+                        Ok(read)
+                    };
+                    print_records(config, filename, read_record, &mut output)?;
                 }
+            },
+        }
+    }
+    stdout.flush()?;
+    output.flush()
+}
+
+fn is_broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    let kind = if let Some(e) = e.downcast_ref::<csv::Error>() {
+        match e.kind() {
+            csv::ErrorKind::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    } else if let Some(e) = e.downcast_ref::<serde_json::Error>() {
+        e.io_error_kind()
+    } else {
+        e.downcast_ref::<io::Error>().map(io::Error::kind)
+    };
+    kind == Some(io::ErrorKind::BrokenPipe)
+}
+
+// 数値と真偽値だけを推定する。先頭のゼロなど、JSON の数値として書けないものは文字列のまま
+fn is_json_literal(value: &str) -> bool {
+    match value {
+        "true" | "false" => true,
+        _ => {
+            value.starts_with(|c: char| c == '-' || c.is_ascii_digit())
+                && value.ends_with(|c: char| c.is_ascii_digit())
+                && serde_json::from_str::<serde_json::Number>(value).is_ok()
+        }
+    }
+}
+
+// lines() と同様に、行末の "\n" や "\r\n" を取り除く
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<bool> {
+    line.clear();
+    if reader.read_line(line)? == 0 {
+        return Ok(false);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(true)
+}
+
+impl Config {
+    // CSV の出力は 1 バイトの区切り文字しか使えないため、それ以外は単純に連結する
+    fn output(&self) -> Output {
+        let csv = |delimiter: u8, quote: u8, escape: Option<u8>| {
+            Output::Csv(Box::new(
+                WriterBuilder::new()
+                    .flexible(true)
+                    .delimiter(delimiter)
+                    .quote(quote)
+                    .escape(escape.unwrap_or(b'\\'))
+                    .double_quote(escape.is_none())
+                    .from_writer(io::stdout()),
+            ))
+        };
+        let joined =
+            |delimiter: &str| Output::Joined(delimiter.to_string(), BufWriter::new(io::stdout()));
+        match (&self.format, &self.mode, self.output_delimiter.as_bytes()) {
+            (Format::Csv, _, _) => csv(b',', b'"', None),
+            (Format::Tsv, _, _) => joined("\t"),
+            (Format::Json, _, _) => Output::Json {
+                names: self.names.clone().unwrap_or_default(),
+                infer_types: self.infer_types,
+                out: BufWriter::new(io::stdout()),
+            },
+            (Format::Default, Mode::Csv { quote, escape, .. }, &[delimiter]) => {
+                csv(delimiter, *quote, *escape)
             }
+            _ => joined(&self.output_delimiter),
         }
     }
+}
+
+// read_record は読み込んだレコードをバッファに書き込み、入力の終わりでは false を返す
+fn print_records(
+    config: &Config,
+    filename: &str,
+    mut read_record: impl FnMut(&mut StringRecord) -> MyResult<bool>,
+    output: &mut Output,
+) -> MyResult<()> {
+    let mut pos = match &config.extract {
+        Fields(pos) | FixedWidth { fields: pos, .. } => pos.clone(),
+        _ => vec![],
+    };
+    let mut record = StringRecord::new();
+    // ファイルごとに列の並びが異なることがあるため、名前はヘッダーごとに解決する
+    if config.header && read_record(&mut record)? {
+        if let Names { names, complement } = &config.extract {
+            pos = select_names(names, &record).map_err(|e| format!("{}: {}", filename, e))?;
+            if *complement {
+                pos = self::complement(&pos);
+            }
+        }
+        match output {
+            // JSON ではヘッダーを出力せず、キーとして使う
+            Output::Json { names, .. } if config.names.is_none() => {
+                *names = extract_fields(&record, &pos).map(str::to_string).collect();
+            }
+            Output::Json { .. } => {}
+            _ if !config.hide_header => output.write(extract_fields(&record, &pos))?,
+            _ => {}
+        }
+    }
+    while read_record(&mut record)? {
+        // 区切り文字を含まない行は、フィールドが一つだけになる
+        if config.only_delimited && record.len() < 2 {
+            continue;
+        }
+        output.write(extract_fields(&record, &pos))?;
+    }
     Ok(())
 }
 
@@ -84,8 +484,15 @@ pub fn get_args() -> MyResult<Config> {
                 .short("b")
                 .help("Selected bytes")
                 .takes_value(true)
+                .allow_hyphen_values(true)
                 .conflicts_with_all(&["fields", "chars"]),
         )
+        .arg(
+            Arg::with_name("no_split")
+                .short("n")
+                .help("Don't split multibyte characters with --bytes")
+                .requires("bytes"),
+        )
         .arg(
             Arg::with_name("chars")
                 .value_name("CHARS")
@@ -93,8 +500,22 @@ pub fn get_args() -> MyResult<Config> {
                 .short("c")
                 .help("Selected characters")
                 .takes_value(true)
+                .allow_hyphen_values(true)
                 .conflicts_with_all(&["fields", "bytes"]),
         )
+        .arg(
+            Arg::with_name("graphemes")
+                .long("graphemes")
+                .help("Count grapheme clusters as characters with --chars")
+                .requires("chars"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .help("Select display columns with --chars")
+                .requires("chars")
+                .conflicts_with("graphemes"),
+        )
         .arg(
             Arg::with_name("fields")
                 .value_name("FIELDS")
@@ -102,8 +523,29 @@ pub fn get_args() -> MyResult<Config> {
                 .short("f")
                 .help("Selected fields")
                 .takes_value(true)
+                .allow_hyphen_values(true)
+                .conflicts_with_all(&["bytes", "chars"]),
+        )
+        .arg(
+            Arg::with_name("field_names")
+                .value_name("NAMES")
+                .long("field-names")
+                .short("F")
+                .help("Selected fields by header name or glob, in the given order")
+                .takes_value(true)
+                .conflicts_with_all(&["fields", "bytes", "chars"]),
+        )
+        .arg(
+            Arg::with_name("header")
+                .long("header")
+                .help("Treat the first line of each file as a header")
                 .conflicts_with_all(&["bytes", "chars"]),
         )
+        .arg(
+            Arg::with_name("hide_header")
+                .long("hide-header")
+                .help("Don't print the header line"),
+        )
         .arg(
             Arg::with_name("delimiter")
                 .value_name("DELIMITER")
@@ -113,23 +555,242 @@ pub fn get_args() -> MyResult<Config> {
                 .default_value("\t")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("regex_delimiter")
+                .value_name("REGEX")
+                .long("regex-delim")
+                .help("Split fields on matches of REGEX")
+                .takes_value(true)
+                .conflicts_with_all(&["delimiter", "whitespace", "bytes", "chars"]),
+        )
+        .arg(
+            Arg::with_name("whitespace")
+                .long("whitespace")
+                .short("w")
+                .help("Split fields on runs of blanks")
+                .conflicts_with_all(&["delimiter", "regex_delimiter", "bytes", "chars"]),
+        )
+        .arg(
+            Arg::with_name("layout")
+                .value_name("LAYOUT")
+                .long("layout")
+                .help("Fixed-width columns by character position, e.g. name:1-20,amount:21-30")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "layout_file",
+                    "bytes",
+                    "chars",
+                    "regex_delimiter",
+                    "whitespace",
+                    "only_delimited",
+                ]),
+        )
+        .arg(
+            Arg::with_name("layout_file")
+                .value_name("FILE")
+                .long("layout-file")
+                .help("File with one fixed-width column per line")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "bytes",
+                    "chars",
+                    "regex_delimiter",
+                    "whitespace",
+                    "only_delimited",
+                ]),
+        )
+        .arg(
+            Arg::with_name("no_trim")
+                .long("no-trim")
+                .help("Keep the padding of fixed-width columns"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("output")
+                .help("Print the selected fields as CSV, TSV or JSON Lines")
+                .takes_value(true)
+                .possible_values(&["csv", "tsv", "json"])
+                .conflicts_with_all(&["output_delimiter", "bytes", "chars"]),
+        )
+        .arg(
+            Arg::with_name("names")
+                .value_name("NAMES")
+                .long("names")
+                .help("Keys of the selected fields with --output json, e.g. a,b,c")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("infer_types")
+                .long("infer-types")
+                .help("Print numbers and booleans as JSON values with --output json"),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .value_name("MODE")
+                .long("mode")
+                .help("Split fields literally or as CSV [default: csv for --delim ',']")
+                .takes_value(true)
+                .possible_values(&["literal", "csv"]),
+        )
+        .arg(
+            Arg::with_name("quote")
+                .value_name("CHAR")
+                .long("quote")
+                .help("Quote character in CSV mode [default: \"]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("escape")
+                .value_name("CHAR")
+                .long("escape")
+                .help("Escape character in CSV mode, instead of doubled quotes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("comment")
+                .value_name("CHAR")
+                .long("comment")
+                .help("Skip lines starting with CHAR in CSV mode")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output_delimiter")
+                .value_name("OUTPUT_DELIMITER")
+                .long("output-delimiter")
+                .help("Delimiter used to join the selected fields")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("only_delimited")
+                .long("only-delimited")
+                .short("s")
+                .help("Don't print lines without delimiters"),
+        )
+        .arg(
+            Arg::with_name("preserve_order")
+                .long("preserve-order")
+                .help("Print positions in the order given, including duplicates"),
+        )
+        .arg(
+            Arg::with_name("complement")
+                .long("complement")
+                .help("Select all but the given positions"),
+        )
         .get_matches();
 
-    let delimiter = matches.value_of("delimiter").unwrap();
-    let delimiter = if delimiter.len() == 1 {
-        delimiter.as_bytes()[0]
+    let delimiter = if let Some(regex) = matches.value_of("regex_delimiter") {
+        Regex::new(regex)
+            .map(Delimiter::Regex)
+            .map_err(|_| format!("Invalid --regex-delim \"{}\"", regex))?
+    } else if matches.is_present("whitespace") {
+        Delimiter::Whitespace
     } else {
-        return Err(format!("--delim \"{}\" must be a single byte", delimiter).into());
+        match matches.value_of("delimiter").unwrap() {
+            "" => return Err("--delim \"\" must not be empty".into()),
+            d if d.len() == 1 => Delimiter::Byte(d.as_bytes()[0]),
+            d => Delimiter::Str(d.to_string()),
+        }
+    };
+    // 引用符を解釈するのは、明示した場合とカンマ区切りの場合だけにする
+    let mode = match (matches.value_of("mode"), &delimiter) {
+        (Some("literal"), _) => Mode::Literal,
+        (Some(_), Delimiter::Byte(_)) | (None, Delimiter::Byte(b',')) => Mode::Csv {
+            quote: parse_byte(matches.value_of("quote"), "--quote")?.unwrap_or(b'"'),
+            escape: parse_byte(matches.value_of("escape"), "--escape")?,
+            comment: parse_byte(matches.value_of("comment"), "--comment")?,
+        },
+        (Some(_), _) => return Err("--mode csv requires a single-byte --delim".into()),
+        (None, _) => Mode::Literal,
     };
+    // csv の Writer は 1 バイトの区切り文字しか使えず、引用符で囲めなくなる
+    if matches.value_of("mode") == Some("csv")
+        && matches
+            .value_of("output_delimiter")
+            .is_some_and(|d| d.len() != 1)
+    {
+        return Err("--mode csv requires a single-byte --output-delimiter".into());
+    }
+    if mode == Mode::Literal
+        && ["quote", "escape", "comment"]
+            .iter()
+            .any(|name| matches.is_present(name))
+    {
+        return Err("--quote, --escape and --comment require --mode csv".into());
+    }
+    let output_delimiter = match &delimiter {
+        Delimiter::Byte(b) => char::from(*b).to_string(),
+        Delimiter::Str(s) => s.clone(),
+        Delimiter::Regex(_) | Delimiter::Whitespace => " ".to_string(),
+    };
+
+    let parse = |value: &str| {
+        parse_pos(value).map(|pos| {
+            if matches.is_present("complement") {
+                complement(&pos)
+            } else if matches.is_present("preserve_order") {
+                pos
+            } else {
+                merge(&pos)
+            }
+        })
+    };
+    let layout = match (matches.value_of("layout"), matches.value_of("layout_file")) {
+        (Some(spec), _) => Some(parse_layout(spec)?),
+        (_, Some(filename)) => {
+            let spec =
+                std::fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            Some(parse_layout(&spec).map_err(|e| format!("{}: {}", filename, e))?)
+        }
+        _ => None,
+    };
+
+    let format = match matches.value_of("format") {
+        Some("csv") => Format::Csv,
+        Some("tsv") => Format::Tsv,
+        Some("json") => Format::Json,
+        _ => Format::Default,
+    };
+    if format != Format::Json && (matches.is_present("names") || matches.is_present("infer_types"))
+    {
+        return Err("--names and --infer-types require --output json".into());
+    }
+    let mut names = matches.value_of("names").map(parse_names).transpose()?;
 
-    let extract = if let Some(field) = matches.value_of("fields") {
-        let fields = parse_pos(field)?;
+    let extract = if let Some((layout_names, layout)) = layout {
+        let header = StringRecord::from(layout_names);
+        let fields = if let Some(field) = matches.value_of("fields") {
+            parse(field)?
+        } else if let Some(selected) = matches.value_of("field_names") {
+            let fields = select_names(&parse_names(selected)?, &header)?;
+            if matches.is_present("complement") {
+                complement(&fields)
+            } else {
+                fields
+            }
+        } else {
+            (0..layout.len()).map(|i| i..i + 1).collect()
+        };
+        // 固定幅の列の名前は、JSON のキーにも使う
+        names.get_or_insert_with(|| {
+            extract_fields(&header, &fields)
+                .map(str::to_string)
+                .collect()
+        });
+        Extract::FixedWidth { layout, fields }
+    } else if let Some(field) = matches.value_of("fields") {
+        let fields = parse(field)?;
         Extract::Fields(fields)
+    } else if let Some(names) = matches.value_of("field_names") {
+        Extract::Names {
+            names: parse_names(names)?,
+            complement: matches.is_present("complement"),
+        }
     } else if let Some(bytes) = matches.value_of("bytes") {
-        let bytes = parse_pos(bytes)?;
+        let bytes = parse(bytes)?;
         Extract::Bytes(bytes)
     } else if let Some(chars) = matches.value_of("chars") {
-        let chars = parse_pos(chars)?;
+        let chars = parse(chars)?;
         Extract::Chars(chars)
     } else {
         return Err("Must have --fields, --bytes, or --chars".into());
@@ -142,6 +803,26 @@ pub fn get_args() -> MyResult<Config> {
             .map(|s| s.to_string())
             .collect(),
         delimiter,
+        mode,
+        format,
+        output_delimiter: matches
+            .value_of("output_delimiter")
+            .map_or(output_delimiter, str::to_string),
+        only_delimited: matches.is_present("only_delimited"),
+        trim: !matches.is_present("no_trim"),
+        header: matches.is_present("header")
+            || matches.is_present("field_names") && !matches!(extract, FixedWidth { .. }),
+        hide_header: matches.is_present("hide_header"),
+        no_split: matches.is_present("no_split"),
+        char_unit: if matches.is_present("graphemes") {
+            CharUnit::Grapheme
+        } else if matches.is_present("columns") {
+            CharUnit::Column
+        } else {
+            CharUnit::Scalar
+        },
+        names,
+        infer_types: matches.is_present("infer_types"),
         extract,
     })
 }
@@ -153,52 +834,148 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
-fn extract_chars(line: &str, char_pos: &[Range<usize>]) -> String {
-    char_pos
+fn extract_chars<'a>(line: &'a str, plan: &'a mut Plan) -> impl Iterator<Item = &'a str> + 'a {
+    plan.select(line, line.char_indices().map(|(i, _)| i))
+}
+
+fn extract_graphemes<'a>(line: &'a str, plan: &'a mut Plan) -> impl Iterator<Item = &'a str> + 'a {
+    plan.select(line, line.grapheme_indices(true).map(|(i, _)| i))
+}
+
+// 全角文字は 2 列を占める。範囲の境界をまたぐ文字は含めない
+fn extract_columns<'a>(line: &'a str, plan: &'a mut Plan) -> impl Iterator<Item = &'a str> + 'a {
+    let mut column = 0;
+    let spans = line.grapheme_indices(true).map(move |(i, g)| {
+        let start = column;
+        column += g.width();
+        // 幅のない文字は、その位置の列に含まれるものとする
+        (start..column.max(start + 1), i)
+    });
+    plan.select_spans(line, spans)
+}
+
+fn extract_bytes<'a>(
+    line: &'a [u8],
+    byte_pos: &'a [Range<usize>],
+) -> impl Iterator<Item = &'a [u8]> + 'a {
+    byte_pos
         .iter()
-        .map(|pos| {
-            line.chars()
-                .enumerate()
-                .filter(|(i, _)| pos.contains(i))
-                .map(|(_, c)| c)
-                .collect::<String>()
-        })
-        .collect::<Vec<_>>()
-        .join("")
+        .map(|pos| &line[pos.start.min(line.len())..pos.end.min(line.len())])
 }
 
-fn extract_bytes(line: &str, byte_pos: &[Range<usize>]) -> String {
+// POSIX の cut -n と同様に、範囲の先頭で途中から始まる文字は全体を含め、
+// 末尾で途中までしか含まれない文字は除く
+fn char_boundaries(line: &[u8], byte_pos: &[Range<usize>]) -> PositionList {
+    let is_continuation = |i: usize| line.get(i).is_some_and(|b| b & 0xc0 == 0x80);
     byte_pos
         .iter()
-        .map(|pos| {
-            String::from_utf8_lossy(
-                line.as_bytes()
-                    .iter()
-                    .enumerate()
-                    .filter(|(i, _)| pos.contains(i))
-                    .map(|(_, b)| b.clone())
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            )
-            .to_string()
+        .filter_map(|pos| {
+            let mut start = pos.start.min(line.len());
+            while start > 0 && is_continuation(start) {
+                start -= 1;
+            }
+            let mut end = pos.end.min(line.len());
+            while end > start && is_continuation(end) {
+                end -= 1;
+            }
+            (start < end).then_some(start..end)
         })
-        .collect::<Vec<_>>()
-        .join("")
+        .collect()
 }
 
-fn extract_fields(record: &StringRecord, field_pos: &[Range<usize>]) -> Vec<String> {
+fn extract_fields<'a>(
+    record: &'a StringRecord,
+    field_pos: &'a [Range<usize>],
+) -> impl Iterator<Item = &'a str> + Clone + 'a {
     field_pos
         .iter()
-        .map(|pos| {
-            record
-                .iter()
-                .enumerate()
-                .filter(|(i, _)| pos.contains(i))
-                .map(|(_, f)| f.to_string())
-                .collect::<Vec<_>>()
+        .flat_map(|pos| (pos.start..pos.end.min(record.len())).map(|i| &record[i]))
+}
+
+// "name:1-20" の形式の列を、カンマか改行で区切って並べる。"#" 以降はコメント
+fn parse_layout(spec: &str) -> MyResult<(Vec<String>, PositionList)> {
+    let mut names = Vec::new();
+    let mut layout = Vec::new();
+    for column in spec
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|column| !column.is_empty())
+    {
+        let invalid = || format!("invalid layout column \"{}\"", column);
+        let (name, range) = column.split_once(':').ok_or_else(invalid)?;
+        let (name, range) = (name.trim(), range.trim());
+        if name.is_empty() || range.is_empty() {
+            return Err(invalid().into());
+        }
+        names.push(name.to_string());
+        layout.extend(parse_pos(range)?);
+    }
+    if layout.is_empty() {
+        return Err("empty layout".into());
+    }
+    Ok((names, layout))
+}
+
+fn parse_byte(value: Option<&str>, flag: &str) -> MyResult<Option<u8>> {
+    match value {
+        None => Ok(None),
+        Some(v) if v.len() == 1 => Ok(Some(v.as_bytes()[0])),
+        Some(v) => Err(format!("{} \"{}\" must be a single byte", flag, v).into()),
+    }
+}
+
+fn parse_names(names: &str) -> MyResult<Vec<String>> {
+    names
+        .split(',')
+        .map(str::trim)
+        .map(|name| match name {
+            "" => Err("empty field in name list".into()),
+            _ => Ok(name.to_string()),
+        })
+        .collect()
+}
+
+// 名前ごとに一致した列を、ヘッダーでの順に並べる。同じ列は一度だけ選ぶ
+fn select_names(names: &[String], header: &StringRecord) -> MyResult<PositionList> {
+    let mut result: PositionList = Vec::new();
+    for name in names {
+        let pattern = glob_to_regex(name);
+        let matched: Vec<_> = header
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| pattern.is_match(column))
+            .map(|(i, _)| i..i + 1)
+            .collect();
+        if matched.is_empty() {
+            return Err(format!(
+                "unknown column \"{}\" (available: {})",
+                name,
+                header.iter().collect::<Vec<_>>().join(", ")
+            )
+            .into());
+        }
+        for range in matched {
+            if !result.contains(&range) {
+                result.push(range);
+            }
+        }
+    }
+    Ok(result)
+}
+
+// "*" と "?" だけを特別扱いする
+fn glob_to_regex(glob: &str) -> Regex {
+    let pattern: String = glob
+        .chars()
+        .map(|c| match c {
+            '*' => ".*".to_string(),
+            '?' => ".".to_string(),
+            c => regex::escape(&c.to_string()),
         })
-        .flatten()
-        .collect::<Vec<_>>()
+        .collect();
+    Regex::new(&format!("^{}$", pattern)).expect("escaped glob is a valid regex")
 }
 
 fn parse_number(value: &str) -> MyResult<usize> {
@@ -237,14 +1014,22 @@ fn parse_pos(range: &str) -> MyResult<PositionList> {
                 // 数値以外が含まれていないか確認
                 if start.chars().any(|c| !c.is_ascii_digit())
                     || end.chars().any(|c| !c.is_ascii_digit())
+                    || start.is_empty() && end.is_empty()
                 {
                     return Err(format!("illegal list value: \"{}\"", part).into());
                 }
 
-                let start_num = parse_number(start)?;
-                let end_num = parse_number(end)?;
+                // 開始を省略した場合は先頭から、終了を省略した場合は末尾まで
+                let start_num = match *start {
+                    "" => 1,
+                    _ => parse_number(start)?,
+                };
+                let end_num = match *end {
+                    "" => usize::MAX,
+                    _ => parse_number(end)?,
+                };
 
-                if start_num >= end_num {
+                if start_num > end_num {
                     return Err(format!(
                         "First number in range ({}) must be lower than second number ({})",
                         start_num, end_num
@@ -264,10 +1049,74 @@ fn parse_pos(range: &str) -> MyResult<PositionList> {
     Ok(result)
 }
 
+// GNU cut と同様に、入力の順に並べて重複や隣接する範囲をまとめる
+fn merge(pos: &[Range<usize>]) -> PositionList {
+    let mut sorted = pos.to_vec();
+    sorted.sort_by_key(|range| range.start);
+
+    let mut result: PositionList = Vec::new();
+    for range in sorted {
+        match result.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => result.push(range),
+        }
+    }
+    result
+}
+
+// 指定された位置以外を、先頭から順に並べた範囲のリストにする
+fn complement(pos: &[Range<usize>]) -> PositionList {
+    let mut result = Vec::new();
+    let mut next = 0;
+    for range in merge(pos) {
+        if range.start > next {
+            result.push(next..range.start);
+        }
+        next = range.end;
+    }
+    if next < usize::MAX {
+        result.push(next..usize::MAX);
+    }
+    result
+}
+
 #[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
 mod unit_tests {
-    use super::{extract_bytes, extract_chars, extract_fields, parse_pos};
+    use super::{
+        Delimiter, Plan, char_boundaries, complement, extract_bytes, extract_chars,
+        extract_columns, extract_fields, extract_graphemes, is_json_literal, merge, parse_layout,
+        parse_pos, select_names,
+    };
     use csv::StringRecord;
+    use regex::Regex;
+    use std::ops::Range;
+
+    fn chars(line: &str, pos: &[Range<usize>]) -> String {
+        extract_chars(line, &mut Plan::new(pos)).collect()
+    }
+
+    fn graphemes(line: &str, pos: &[Range<usize>]) -> String {
+        extract_graphemes(line, &mut Plan::new(pos)).collect()
+    }
+
+    fn columns(line: &str, pos: &[Range<usize>]) -> String {
+        extract_columns(line, &mut Plan::new(pos)).collect()
+    }
+
+    fn bytes(line: &[u8], pos: &[Range<usize>]) -> Vec<u8> {
+        extract_bytes(line, pos).flatten().copied().collect()
+    }
+
+    fn fields<'a>(record: &'a StringRecord, pos: &'a [Range<usize>]) -> Vec<&'a str> {
+        extract_fields(record, pos).collect()
+    }
+
+    fn split(delimiter: Delimiter, line: &str) -> StringRecord {
+        let mut record = StringRecord::new();
+        delimiter.split(line, &mut record);
+        record
+    }
 
     #[test]
     fn test_parse_pos() {
@@ -326,23 +1175,21 @@ mod unit_tests {
         let res = parse_pos("1,");
         assert!(res.is_err());
 
-        let res = parse_pos("1-");
-        assert!(res.is_err());
-
         let res = parse_pos("1-1-1");
         assert!(res.is_err());
 
         let res = parse_pos("1-1-a");
         assert!(res.is_err());
 
-        //最初の数字は２番目より小さい必要がある
-        let res = parse_pos("1-1");
+        let res = parse_pos("--1");
         assert!(res.is_err());
-        assert_eq!(
-            res.unwrap_err().to_string(),
-            "First number in range (1) must be lower than second number (1)",
-        );
+        assert_eq!(res.unwrap_err().to_string(), "illegal list value: \"--1\"",);
 
+        let res = parse_pos("-0");
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().to_string(), "illegal list value: \"0\"",);
+
+        //最初の数字は２番目より大きくてはいけない
         let res = parse_pos("2-1");
         assert!(res.is_err());
         assert_eq!(
@@ -382,35 +1229,192 @@ mod unit_tests {
         let res = parse_pos("15, 19-20");
         assert!(res.is_ok());
         assert_eq!(res.unwrap(), vec![14..15, 18..20]);
+
+        let res = parse_pos("1-1");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), vec![0..1]);
+
+        // 開始や終了は省略できる
+        let res = parse_pos("3-");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), vec![2..usize::MAX]);
+
+        let res = parse_pos("-5");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), vec![0..5]);
+
+        let res = parse_pos("-2,4-");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), vec![0..2, 3..usize::MAX]);
+    }
+
+    #[test]
+    fn test_split() {
+        let rec = split(Delimiter::Str("::".to_string()), "a::b::::c");
+        assert_eq!(rec, StringRecord::from(vec!["a", "b", "", "c"]));
+        let rec = split(Delimiter::Regex(Regex::new(r"\s+").unwrap()), "a b\t c");
+        assert_eq!(rec, StringRecord::from(vec!["a", "b", "c"]));
+        let rec = split(Delimiter::Whitespace, "  a  b ");
+        assert_eq!(rec, StringRecord::from(vec!["a", "b"]));
+        let rec = split(Delimiter::Whitespace, "");
+        assert_eq!(rec.len(), 0);
+    }
+
+    #[test]
+    fn test_select_names() {
+        let header = StringRecord::from(vec!["id", "addr_city", "name", "addr_zip"]);
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            select_names(&names(&["name", "id"]), &header).unwrap(),
+            vec![2..3, 0..1]
+        );
+        assert_eq!(
+            select_names(&names(&["addr_*", "addr_zip"]), &header).unwrap(),
+            vec![1..2, 3..4]
+        );
+        assert_eq!(select_names(&names(&["?d"]), &header).unwrap(), vec![0..1]);
+        assert_eq!(
+            select_names(&names(&["a.*"]), &header)
+                .unwrap_err()
+                .to_string(),
+            "unknown column \"a.*\" (available: id, addr_city, name, addr_zip)"
+        );
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        let (names, layout) = parse_layout("name:1-20, amount:21-30,rest:31-").unwrap();
+        assert_eq!(names, ["name", "amount", "rest"]);
+        assert_eq!(layout, vec![0..20, 20..30, 30..usize::MAX]);
+
+        let (names, layout) = parse_layout("# layout\nid:1-4\n\nname : 5-9 # padded\n").unwrap();
+        assert_eq!(names, ["id", "name"]);
+        assert_eq!(layout, vec![0..4, 4..9]);
+
+        for (bad, message) in [
+            ("name", "invalid layout column \"name\""),
+            (":1-2", "invalid layout column \":1-2\""),
+            ("name:", "invalid layout column \"name:\""),
+            ("name:x", "illegal list value: \"x\""),
+            ("# nothing", "empty layout"),
+        ] {
+            let res = parse_layout(bad);
+            assert!(res.is_err(), "{}", bad);
+            assert_eq!(res.unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_is_json_literal() {
+        for value in ["0", "-12", "3.5", "1e-3", "true", "false"] {
+            assert!(is_json_literal(value), "{}", value);
+        }
+        for value in [
+            "", "007", "+1", "1.", ".5", " 1", "1 ", "NaN", "True", "0x10",
+        ] {
+            assert!(!is_json_literal(value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        assert_eq!(merge(&[0..1]), vec![0..1]);
+        assert_eq!(merge(&[2..3, 0..1]), vec![0..1, 2..3]);
+        assert_eq!(merge(&[0..1, 0..1]), vec![0..1]);
+        assert_eq!(merge(&[0..1, 1..2]), vec![0..2]);
+        assert_eq!(merge(&[3..usize::MAX, 0..5]), vec![0..usize::MAX]);
+        assert_eq!(merge(&[4..6, 0..2, 1..3]), vec![0..3, 4..6]);
+    }
+
+    #[test]
+    fn test_complement() {
+        assert_eq!(complement(&[0..1]), vec![1..usize::MAX]);
+        assert_eq!(complement(&[1..2]), vec![0..1, 2..usize::MAX]);
+        assert_eq!(complement(&[4..6, 0..2, 1..3]), vec![3..4, 6..usize::MAX]);
+        assert_eq!(complement(&[2..usize::MAX]), vec![0..2]);
+        assert!(complement(&[0..usize::MAX]).is_empty());
     }
 
     #[test]
     fn test_extract_chars() {
-        assert_eq!(extract_chars("", &[0..1]), "".to_string());
-        assert_eq!(extract_chars("ábc", &[0..1]), "á".to_string());
-        assert_eq!(extract_chars("ábc", &[0..1, 2..3]), "ác".to_string());
-        assert_eq!(extract_chars("ábc", &[0..3]), "ábc".to_string());
-        assert_eq!(extract_chars("ábc", &[2..3, 1..2]), "cb".to_string());
-        assert_eq!(extract_chars("ábc", &[0..1, 1..2, 4..5]), "áb".to_string());
+        assert_eq!(chars("", &[0..1]), "".to_string());
+        assert_eq!(chars("ábc", &[0..1]), "á".to_string());
+        assert_eq!(chars("ábc", &[0..1, 2..3]), "ác".to_string());
+        assert_eq!(chars("ábc", &[0..3]), "ábc".to_string());
+        assert_eq!(chars("ábc", &[2..3, 1..2]), "cb".to_string());
+        assert_eq!(chars("ábc", &[0..1, 1..2, 4..5]), "áb".to_string());
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut plan = Plan::new(&[3..usize::MAX, 0..2, 1..4, 0..2]);
+        assert_eq!(plan.bounds, [0, 1, 2, 3, 4, usize::MAX]);
+        let line = "abcdef";
+        let selected: Vec<_> = plan
+            .select(line, line.char_indices().map(|(i, _)| i))
+            .collect();
+        assert_eq!(selected, ["def", "ab", "bcd", "ab"]);
+        // 前の行より短い行でも、範囲は行末までに収まる
+        let line = "xy";
+        let selected: Vec<_> = plan
+            .select(line, line.char_indices().map(|(i, _)| i))
+            .collect();
+        assert_eq!(selected, ["", "xy", "y", "xy"]);
+    }
+
+    #[test]
+    fn test_extract_graphemes() {
+        let line = "e\u{301}👍🏽x";
+        assert_eq!(graphemes(line, &[0..1]), "e\u{301}");
+        assert_eq!(graphemes(line, &[1..2]), "👍🏽");
+        assert_eq!(graphemes(line, &[2..usize::MAX]), "x");
+        assert_eq!(graphemes(line, &[2..3, 0..1]), "xe\u{301}");
+        assert_eq!(chars(line, &[0..1]), "e");
+    }
+
+    #[test]
+    fn test_extract_columns() {
+        let line = "ab日本語c";
+        assert_eq!(columns(line, &[0..4]), "ab日");
+        assert_eq!(columns(line, &[0..5]), "ab日");
+        assert_eq!(columns(line, &[3..9]), "本語c");
+        assert_eq!(columns(line, &[2..usize::MAX]), "日本語c");
+        assert_eq!(columns(line, &[3..4]), "");
+        assert_eq!(columns(line, &[8..9, 0..2]), "cab");
+        assert_eq!(columns("a\u{301}b", &[0..1]), "a\u{301}");
+        assert_eq!(columns("\u{200b}ab", &[0..1]), "\u{200b}a");
     }
 
     #[test]
     fn test_extract_bytes() {
-        assert_eq!(extract_bytes("ábc", &[0..1]), "�".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..2]), "á".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..3]), "áb".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..4]), "ábc".to_string());
-        assert_eq!(extract_bytes("ábc", &[3..4, 2..3]), "cb".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..2, 5..6]), "á".to_string());
+        assert_eq!(bytes("ábc".as_bytes(), &[0..1]), b"\xc3");
+        assert_eq!(bytes("ábc".as_bytes(), &[0..2]), "á".as_bytes());
+        assert_eq!(bytes("ábc".as_bytes(), &[0..3]), "áb".as_bytes());
+        assert_eq!(bytes("ábc".as_bytes(), &[0..4]), "ábc".as_bytes());
+        assert_eq!(bytes("ábc".as_bytes(), &[3..4, 2..3]), b"cb");
+        assert_eq!(bytes("ábc".as_bytes(), &[0..2, 5..6]), "á".as_bytes());
+        assert_eq!(bytes(b"\xff\xfeab", &[1..3]), b"\xfea");
+    }
+
+    #[test]
+    fn test_char_boundaries() {
+        let line = "aáb日c".as_bytes();
+        assert_eq!(char_boundaries(line, &[0..2]), vec![0..1]);
+        assert_eq!(char_boundaries(line, &[2..4]), vec![1..4]);
+        assert_eq!(char_boundaries(line, &[2..3]), vec![1..3]);
+        assert_eq!(char_boundaries(line, &[3..6]), vec![3..4]);
+        assert!(char_boundaries(line, &[5..6]).is_empty());
+        assert_eq!(char_boundaries(line, &[5..usize::MAX]), vec![4..8]);
+        assert!(char_boundaries(line, &[20..usize::MAX]).is_empty());
     }
 
     #[test]
     fn test_extract_fields() {
         let rec = StringRecord::from(vec!["Captain", "Sham", "12345"]);
-        assert_eq!(extract_fields(&rec, &[0..1]), &["Captain"]);
-        assert_eq!(extract_fields(&rec, &[1..2]), &["Sham"]);
-        assert_eq!(extract_fields(&rec, &[0..1, 2..3]), &["Captain", "12345"]);
-        assert_eq!(extract_fields(&rec, &[0..1, 3..4]), &["Captain"]);
-        assert_eq!(extract_fields(&rec, &[1..2, 0..1]), &["Sham", "Captain"]);
+        assert_eq!(fields(&rec, &[0..1]), &["Captain"]);
+        assert_eq!(fields(&rec, &[1..2]), &["Sham"]);
+        assert_eq!(fields(&rec, &[0..1, 2..3]), &["Captain", "12345"]);
+        assert_eq!(fields(&rec, &[0..1, 3..4]), &["Captain"]);
+        assert_eq!(fields(&rec, &[1..2, 0..1]), &["Sham", "Captain"]);
     }
 }
@@ -35,7 +35,7 @@ fn skips_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&["-f", "1", CSV, &bad, TSV])
+        .args(["-f", "1", CSV, &bad, TSV])
         .assert()
         .success()
         .stderr(predicate::str::is_match(expected)?);
@@ -93,7 +93,7 @@ fn dies_bad_digit_chars() -> TestResult {
 fn dies_empty_delimiter() -> TestResult {
     dies(
         &[CSV, "-f", "1", "-d", ""],
-        "--delim \"\" must be a single byte",
+        "--delim \"\" must not be empty",
     )
 }
 
@@ -101,8 +101,8 @@ fn dies_empty_delimiter() -> TestResult {
 #[test]
 fn dies_bad_delimiter() -> TestResult {
     dies(
-        &[CSV, "-f", "1", "-d", ",,"],
-        "--delim \",,\" must be a single byte",
+        &[CSV, "-f", "1", "--regex-delim", "("],
+        "Invalid --regex-delim \"(\"",
     )
 }
 
@@ -110,7 +110,7 @@ fn dies_bad_delimiter() -> TestResult {
 #[test]
 fn dies_chars_bytes_fields() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&[CSV, "-c", "1", "-f", "1", "-b", "1"])
+        .args([CSV, "-c", "1", "-f", "1", "-b", "1"])
         .assert()
         .failure();
     Ok(())
@@ -120,7 +120,7 @@ fn dies_chars_bytes_fields() -> TestResult {
 #[test]
 fn dies_bytes_fields() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&[CSV, "-f", "1", "-b", "1"])
+        .args([CSV, "-f", "1", "-b", "1"])
         .assert()
         .failure();
     Ok(())
@@ -130,7 +130,7 @@ fn dies_bytes_fields() -> TestResult {
 #[test]
 fn dies_chars_fields() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&[CSV, "-c", "1", "-f", "1"])
+        .args([CSV, "-c", "1", "-f", "1"])
         .assert()
         .failure();
     Ok(())
@@ -140,7 +140,7 @@ fn dies_chars_fields() -> TestResult {
 #[test]
 fn dies_chars_bytes() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&[CSV, "-c", "1", "-b", "1"])
+        .args([CSV, "-c", "1", "-b", "1"])
         .assert()
         .failure();
     Ok(())
@@ -159,14 +159,13 @@ fn run(args: &[&str], expected_file: &str) -> TestResult {
 }
 
 // --------------------------------------------------
-fn run_lossy(args: &[&str], expected_file: &str) -> TestResult {
-    let contents = fs::read(expected_file)?;
-    let expected = String::from_utf8_lossy(&contents);
+fn run_bytes(args: &[&str], expected_file: &str) -> TestResult {
+    let expected = fs::read(expected_file)?;
     Command::cargo_bin(PRG)?
         .args(args)
         .assert()
         .success()
-        .stdout(predicate::str::contains(expected));
+        .stdout(expected);
     Ok(())
 }
 
@@ -275,7 +274,7 @@ fn tsv_b2() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn tsv_b8() -> TestResult {
-    run_lossy(&[TSV, "-b", "8"], "tests/expected/movies1.tsv.b8.out")
+    run_bytes(&[TSV, "-b", "8"], "tests/expected/movies1.tsv.b8.out")
 }
 
 // --------------------------------------------------
@@ -293,7 +292,7 @@ fn tsv_b2_3() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn tsv_b1_8() -> TestResult {
-    run_lossy(&[TSV, "-b", "1-8"], "tests/expected/movies1.tsv.b1-8.out")
+    run_bytes(&[TSV, "-b", "1-8"], "tests/expected/movies1.tsv.b1-8.out")
 }
 
 // --------------------------------------------------
@@ -335,5 +334,420 @@ fn tsv_c1_8() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn repeated_value() -> TestResult {
-    run(&[BOOKS, "-c", "1,1"], "tests/expected/books.c1,1.out")
+    run(
+        &[BOOKS, "-c", "1,1", "--preserve-order"],
+        "tests/expected/books.c1,1.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn open_ended_start() -> TestResult {
+    run(&[BOOKS, "-f", "2-"], "tests/expected/books.f2-.out")?;
+    run(&[BOOKS, "-c", "3-"], "tests/expected/books.c3-.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn open_ended_end() -> TestResult {
+    run(&[BOOKS, "-f", "-2"], "tests/expected/books.f-2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn single_element_range() -> TestResult {
+    run(&[BOOKS, "-c", "1-1"], "tests/expected/books.c1-1.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn complement() -> TestResult {
+    run(
+        &[BOOKS, "-f", "2", "--complement"],
+        "tests/expected/books.f2.complement.out",
+    )?;
+    run(
+        &[BOOKS, "-c", "-3", "--complement"],
+        "tests/expected/books.c-3.complement.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn input_order() -> TestResult {
+    run(&[BOOKS, "-c", "1,1"], "tests/expected/books.c1.out")?;
+    run(&[BOOKS, "-f", "3,1,1-2"], BOOKS)
+}
+
+// --------------------------------------------------
+#[test]
+fn preserve_order() -> TestResult {
+    run(
+        &[BOOKS, "-f", "3,1", "--preserve-order"],
+        "tests/expected/books.f3,1.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn output_delimiter() -> TestResult {
+    run(
+        &[BOOKS, "-f", "1,3", "--output-delimiter", " | "],
+        "tests/expected/books.f1,3.output_delimiter.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn only_delimited() -> TestResult {
+    let input = "a,b,c\nno delimiter\nd,e\n";
+    Command::cargo_bin(PRG)?
+        .args(["-d", ",", "-f", "2"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("b\n\ne\n");
+    Command::cargo_bin(PRG)?
+        .args(["-d", ",", "-f", "2", "-s"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("b\ne\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_delimiter_conflicts() -> TestResult {
+    dies(&[CSV, "-f", "1", "-d", ",", "-w"], "cannot be used with")?;
+    dies(
+        &[CSV, "-f", "1", "-w", "--regex-delim", ","],
+        "cannot be used with",
+    )
+}
+
+// --------------------------------------------------
+fn run_stdin(args: &[&str], input: &str, expected: &str) -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(expected.to_string());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multibyte_delimiter() -> TestResult {
+    run_stdin(&["-d", "::", "-f", "2-"], "a::b::c\n", "b::c\n")?;
+    run_stdin(&["-d", "→", "-f", "1,3"], "á→b→ç\n", "á→ç\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_delimiter() -> TestResult {
+    run_stdin(
+        &["--regex-delim", r"\s*;\s*", "-f", "1,3"],
+        "a ; b;c  ;d\n",
+        "a c\n",
+    )?;
+    run_stdin(
+        &[
+            "--regex-delim",
+            "[0-9]+",
+            "-f",
+            "2-",
+            "--output-delimiter",
+            ",",
+        ],
+        "a1b22c\n",
+        "b,c\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn whitespace() -> TestResult {
+    run_stdin(
+        &["-w", "-f", "1,3"],
+        "  Name    Size  Date\nfoo\t 10   2024\n",
+        "Name Date\nfoo 2024\n",
+    )?;
+    run_stdin(&["-w", "-f", "2", "-s"], "single\na b\n", "b\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn field_names() -> TestResult {
+    run_stdin(
+        &["-F", "Title,Author", BOOKS, "-"],
+        "Year\tTitle\tAuthor\n2001\tSpace Odyssey\tArthur C. Clarke\n",
+        "Title\tAuthor\n\
+         La Confession de Claude\tÉmile Zola\n\
+         Waiting for Godot\tSamuel Beckett\n\
+         20,000 Leagues Under the Sea\tJules Verne\n\
+         Title\tAuthor\n\
+         Space Odyssey\tArthur C. Clarke\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn field_names_glob() -> TestResult {
+    let input = "id,addr_city,name,addr_zip\n1,Paris,Ann,75001\n";
+    run_stdin(
+        &["-d", ",", "-F", "name,addr_*"],
+        input,
+        "name,addr_city,addr_zip\nAnn,Paris,75001\n",
+    )?;
+    run_stdin(
+        &["-d", ",", "-F", "addr_*", "--complement", "--hide-header"],
+        input,
+        "1,Ann\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn header_with_fields() -> TestResult {
+    run_stdin(
+        &["--header", "--hide-header", "-f", "2"],
+        "a\tb\n1\t2\n",
+        "2\n",
+    )?;
+    run_stdin(&["--header", "-f", "2"], "a\tb\n1\t2\n", "b\n2\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unknown_column() -> TestResult {
+    dies(
+        &[BOOKS, "-F", "Author,Isbn"],
+        "unknown column \"Isbn\" (available: Author, Year, Title)",
+    )?;
+    dies(&[BOOKS, "-F", "Author,"], "empty field in name list")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_requotes_fields() -> TestResult {
+    run(
+        &["tests/inputs/books.csv", "-d", ",", "-f", "3,1"],
+        "tests/expected/books.csv.f3,1.dcomma.out",
+    )?;
+    run_stdin(&["-d", ",", "-f", "1"], "\"x\"\"y\",z\n", "\"x\"\"y\"\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn literal_mode() -> TestResult {
+    let input = "a\t\"b\tc\"\n";
+    // カンマ区切り以外では、既定では引用符を解釈しない
+    run_stdin(&["-f", "2"], input, "\"b\n")?;
+    run_stdin(&["-f", "2", "--mode", "csv"], input, "\"b\tc\"\n")?;
+    run_stdin(
+        &["-d", ",", "-f", "2", "--mode", "literal"],
+        "a,\"b,c\"\n",
+        "\"b\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_escape_and_comment() -> TestResult {
+    run_stdin(
+        &[
+            "-d",
+            ";",
+            "--mode",
+            "csv",
+            "-f",
+            "2,1",
+            "--preserve-order",
+            "--escape",
+            "\\",
+            "--comment",
+            "#",
+        ],
+        "# comment\n\"x\\\"y\";z\n",
+        "z;\"x\\\"y\"\n",
+    )?;
+    run_stdin(
+        &["-d", ",", "-f", "2", "--quote", "'"],
+        "a,'b,c'\n",
+        "'b,c'\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_mode() -> TestResult {
+    dies(
+        &[CSV, "-f", "1", "-d", "::", "--mode", "csv"],
+        "--mode csv requires a single-byte --delim",
+    )?;
+    dies(
+        &[CSV, "-f", "1", "--mode", "literal", "--quote", "'"],
+        "--quote, --escape and --comment require --mode csv",
+    )?;
+    dies(
+        &[CSV, "-f", "1", "--quote", "'"],
+        "--quote, --escape and --comment require --mode csv",
+    )?;
+    dies(
+        &[CSV, "-f", "1", "-d", ",", "--quote", "''"],
+        "--quote \"''\" must be a single byte",
+    )?;
+    dies(
+        &[CSV, "-f", "1", "--mode", "csv", "--output-delimiter", "::"],
+        "--mode csv requires a single-byte --output-delimiter",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bytes_non_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-b", "2-3"])
+        .write_stdin(&b"\xffab\n\xfe\xfdc\n"[..])
+        .assert()
+        .success()
+        .stdout(&b"ab\n\xfdc\n"[..]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn bytes_no_split() -> TestResult {
+    run_stdin(&["-b", "2-4", "-n"], "aáb日c\n", "áb\n")?;
+    run_stdin(&["-b", "3-", "-n"], "aáb\n", "áb\n")?;
+    run_stdin(&["-b", "5-6", "-n"], "aáb日c\n", "\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn graphemes() -> TestResult {
+    let input = "e\u{301}👍🏽x\n";
+    run_stdin(&["-c", "2", "--graphemes"], input, "👍🏽\n")?;
+    run_stdin(&["-c", "1,3", "--graphemes"], input, "e\u{301}x\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn columns() -> TestResult {
+    let input = "名前    金額\nabc     100\n";
+    run_stdin(&["-c", "-8", "--columns"], input, "名前    \nabc     \n")?;
+    run_stdin(&["-c", "9-", "--columns"], input, "金額\n100\n")?;
+    run_stdin(&["-c", "2-3", "--columns"], "日本語\n", "\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_graphemes_columns() -> TestResult {
+    dies(
+        &[CSV, "-c", "1", "--graphemes", "--columns"],
+        "cannot be used with",
+    )?;
+    dies(&[CSV, "-f", "1", "--columns"], "--chars")
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_width() -> TestResult {
+    let layout = "name:1-10,amount:11-18,state:20-";
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/fixed.txt", "--layout", layout])
+        .assert()
+        .success()
+        .stdout("Alice\t00012.50\tNY\nBob,Jr\t00003.00\tCA\n日本\t00100.00\tTK\n");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/fixed.txt", "--layout", layout, "-f", "1"])
+        .arg("--no-trim")
+        .assert()
+        .success()
+        .stdout("Alice     \nBob,Jr    \n日本        \n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_width_layout_file() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/fixed.txt", "--layout-file"])
+        .args([
+            "tests/inputs/fixed.layout",
+            "-F",
+            "state,n*",
+            "--output",
+            "csv",
+        ])
+        .assert()
+        .success()
+        .stdout("NY,Alice\nCA,\"Bob,Jr\"\nTK,日本\n");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/fixed.txt", "--layout-file"])
+        .args(["tests/inputs/fixed.layout", "-F", "amount", "--complement"])
+        .args(["--output", "tsv"])
+        .assert()
+        .success()
+        .stdout("Alice\tNY\nBob,Jr\tCA\n日本\tTK\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_output() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/books.csv", "-d", ",", "-F", "Title,Year"])
+        .args(["--output", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "{\"Title\":\"La Confession de Claude\",\"Year\":\"1865\"}\n",
+        ));
+    run_stdin(
+        &["-f", "1,3", "--output", "json", "--names", "a,c"],
+        "x\ty\tz\"w\n1\n",
+        "{\"a\":\"x\",\"c\":\"z\\\"w\"}\n{\"a\":\"1\"}\n",
+    )?;
+    run_stdin(
+        &["-d", ":", "-f", "1-", "--output", "json", "--infer-types"],
+        "-1.5e3:007:true:1.:x\n",
+        "{\"f1\":-1.5e3,\"f2\":\"007\",\"f3\":true,\"f4\":\"1.\",\"f5\":\"x\"}\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn json_output_layout() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/fixed.txt", "--layout-file"])
+        .args(["tests/inputs/fixed.layout", "-f", "3,1", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "{\"name\":\"Alice\",\"state\":\"NY\"}\n",
+        ));
+    dies(
+        &[CSV, "-f", "1", "--names", "a"],
+        "--names and --infer-types require --output json",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_layout() -> TestResult {
+    dies(
+        &[CSV, "--layout", "name:1-10,amount"],
+        "invalid layout column \"amount\"",
+    )?;
+    dies(
+        &[CSV, "--layout", "name:1-10", "-F", "amount"],
+        "unknown column \"amount\" (available: name)",
+    )?;
+    dies(
+        &[CSV, "--layout", "name:1-10", "-c", "1"],
+        "cannot be used with",
+    )
 }
@@ -0,0 +1,244 @@
+use crate::{format::Format, owner::NameCache, walk::Entry};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+const MAX_BATCH_BYTES: usize = 128 * 1024;
+
+#[derive(Debug)]
+pub enum Action {
+    Print,
+    Print0,
+    Delete,
+    Exec(Exec),
+    Printf(Format),
+    Json(NameCache),
+}
+
+#[derive(Debug)]
+pub struct Exec {
+    argv: Vec<String>,
+    in_dir: bool,
+    confirm: bool,
+    batch: bool,
+    pending: Vec<PathBuf>,
+    pending_bytes: usize,
+    pending_dir: Option<PathBuf>,
+}
+
+impl Action {
+    // 戻り値が false の場合、同じエントリに対する後続のアクションは実行しない
+    pub fn run(&mut self, entry: &Entry, root: &Path) -> MyResult<bool> {
+        match self {
+            Action::Print => print(entry.path(), b'\n'),
+            Action::Print0 => print(entry.path(), b'\0'),
+            Action::Delete => delete(entry, root).map(|_| true),
+            Action::Exec(exec) => exec.run(entry.path()),
+            Action::Printf(format) => write_stdout(&format.render(entry)?),
+            Action::Json(names) => {
+                let mut line = crate::format::json(entry, names)?.into_bytes();
+                line.push(b'\n');
+                write_stdout(&line)
+            }
+        }
+    }
+
+    pub fn finish(&mut self) -> MyResult<()> {
+        match self {
+            Action::Exec(exec) => exec.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Exec {
+    pub fn new(flag: &str, mut argv: Vec<String>, in_dir: bool, confirm: bool) -> MyResult<Exec> {
+        let batch = argv.len() >= 2 && argv[argv.len() - 2..] == ["{}", "+"];
+        if batch {
+            if confirm {
+                return Err(format!("{} does not support \"{{}} +\"", flag).into());
+            }
+            argv.truncate(argv.len() - 2);
+            if argv.iter().any(|arg| arg.contains("{}")) {
+                return Err(format!("{}: only one \"{{}}\" is allowed with \"+\"", flag).into());
+            }
+        }
+        if argv.is_empty() {
+            return Err(format!("{}: missing command", flag).into());
+        }
+        Ok(Exec {
+            argv,
+            in_dir,
+            confirm,
+            batch,
+            pending: vec![],
+            pending_bytes: 0,
+            pending_dir: None,
+        })
+    }
+
+    fn run(&mut self, path: &Path) -> MyResult<bool> {
+        let (dir, arg) = self.target(path);
+
+        if self.batch {
+            if self.pending_dir != dir {
+                self.flush()?;
+                self.pending_dir = dir;
+            }
+            self.pending_bytes += arg.as_os_str().len() + 1;
+            self.pending.push(arg);
+            if self.pending_bytes >= MAX_BATCH_BYTES {
+                self.flush()?;
+            }
+            return Ok(true);
+        }
+
+        let args: Vec<OsString> = self.argv[1..]
+            .iter()
+            .map(|a| replace_braces(a, &arg))
+            .collect();
+        let program = replace_braces(&self.argv[0], &arg);
+        if self.confirm && !confirm(&program, &args)? {
+            return Ok(false);
+        }
+        let mut command = Command::new(program);
+        command.args(args);
+        if let Some(dir) = &dir {
+            command.current_dir(dir);
+        }
+        match command.status() {
+            Ok(status) => Ok(status.success()),
+            Err(e) => {
+                eprintln!("{}: {}", self.argv[0], e);
+                Ok(false)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> MyResult<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut command = Command::new(&self.argv[0]);
+        command.args(&self.argv[1..]).args(self.pending.drain(..));
+        if let Some(dir) = &self.pending_dir {
+            command.current_dir(dir);
+        }
+        self.pending_bytes = 0;
+        match command.status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(format!("{}: {}", self.argv[0], status).into()),
+            Err(e) => Err(format!("{}: {}", self.argv[0], e).into()),
+        }
+    }
+
+    // --execdir ではエントリの親ディレクトリで "./名前" を引数にして実行する
+    fn target(&self, path: &Path) -> (Option<PathBuf>, PathBuf) {
+        if !self.in_dir {
+            return (None, path.to_path_buf());
+        }
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = path.file_name().map_or(path.as_os_str(), |n| n);
+        (Some(dir), Path::new(".").join(name))
+    }
+}
+
+// UTF-8 でないファイル名もそのまま出力する
+fn print(path: &Path, terminator: u8) -> MyResult<bool> {
+    let mut bytes = path.as_os_str().as_encoded_bytes().to_vec();
+    bytes.push(terminator);
+    write_stdout(&bytes)
+}
+
+// 出力先が閉じられた場合の io::Error は、そのまま呼び出し元に返して探索を止めさせる
+fn write_stdout(bytes: &[u8]) -> MyResult<bool> {
+    io::stdout().lock().write_all(bytes)?;
+    Ok(true)
+}
+
+pub fn is_broken_pipe(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+fn replace_braces(arg: &str, path: &Path) -> OsString {
+    if !arg.contains("{}") {
+        return OsString::from(arg);
+    }
+    let mut result = OsString::new();
+    let mut parts = arg.split("{}");
+    if let Some(first) = parts.next() {
+        result.push(first);
+    }
+    for part in parts {
+        result.push(path.as_os_str());
+        result.push(part);
+    }
+    result
+}
+
+fn confirm(program: &OsString, args: &[OsString]) -> MyResult<bool> {
+    let command = std::iter::once(program)
+        .chain(args)
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    eprint!("< {} > ? ", command);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim_start().starts_with(['y', 'Y']))
+}
+
+fn delete(entry: &Entry, root: &Path) -> MyResult<()> {
+    let path = entry.path();
+    // find と同様に、. や .. のような開始パスは何も言わずに残す
+    if path.file_name().is_none() {
+        return Ok(());
+    }
+    ensure_within(path, root, "delete")?;
+    let result = if entry.file_type().is_dir() && !entry.path_is_symlink() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|e| format!("cannot delete {}: {}", path.display(), e).into())
+}
+
+// -L やシンボリックリンクの親ディレクトリを通して、開始パスの外を変更しないようにする
+pub fn ensure_within(path: &Path, root: &Path, verb: &str) -> MyResult<()> {
+    if !is_within(path, root)? {
+        return Err(format!(
+            "{}: refusing to {} outside of {}",
+            path.display(),
+            verb,
+            root.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// シンボリックリンク自体は辿らず、親ディレクトリを正規化して比較する
+// root は正規化済みであること
+fn is_within(path: &Path, root: &Path) -> MyResult<bool> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let resolved = match path.file_name() {
+        Some(name) => fs::canonicalize(parent)?.join(name),
+        None => fs::canonicalize(path)?,
+    };
+    Ok(resolved.starts_with(root))
+}
@@ -0,0 +1,173 @@
+use crate::{action::ensure_within, walk::Entry};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs::{self, File},
+    hash::Hash,
+    io::{self, Read, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+const PARTIAL_BYTES: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dedupe {
+    Hardlink,
+    Delete,
+}
+
+// root は見つけたときの開始パスで、重複を除く際に外へ出ていないかを確かめるのに使う
+#[derive(Debug)]
+pub struct Candidate {
+    path: PathBuf,
+    root: PathBuf,
+    size: u64,
+}
+
+// 探索中に見つかったファイルを集める
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    files: Vec<Candidate>,
+    seen: HashSet<(u64, u64)>,
+}
+
+impl Duplicates {
+    // 空のファイルや、既にハードリンクされているファイルは対象にしない
+    pub fn add(&mut self, entry: &Entry, root: &Path) {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("{}: {}", entry.path().display(), e);
+                return;
+            }
+        };
+        if !metadata.is_file()
+            || metadata.len() == 0
+            || !self.seen.insert((metadata.dev(), metadata.ino()))
+        {
+            return;
+        }
+        self.files.push(Candidate {
+            path: entry.path().to_path_buf(),
+            root: root.to_path_buf(),
+            size: metadata.len(),
+        });
+    }
+
+    // サイズ、先頭部分のハッシュ、全体のハッシュの順に絞り込む
+    pub fn groups(self) -> Vec<Vec<Candidate>> {
+        let by_size = group_by(vec![self.files], |file| Some(file.size));
+        let by_partial = group_by(by_size, |file| hash(&file.path, Some(PARTIAL_BYTES)));
+        // 先頭部分でファイル全体を読み終えている場合は、改めて読む必要はない
+        let (small, large): (Vec<_>, Vec<_>) = by_partial
+            .into_iter()
+            .partition(|group| group[0].size <= PARTIAL_BYTES);
+        let by_full = group_by(large, |file| hash(&file.path, None));
+
+        let mut groups: Vec<Vec<Candidate>> = small
+            .into_iter()
+            .chain(by_full)
+            .map(|mut group| {
+                group.sort_by(|a, b| a.path.cmp(&b.path));
+                group
+            })
+            .collect();
+        groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+        groups
+    }
+}
+
+fn group_by<K, F>(groups: Vec<Vec<Candidate>>, mut key: F) -> Vec<Vec<Candidate>>
+where
+    K: Eq + Hash,
+    F: FnMut(&Candidate) -> Option<K>,
+{
+    let mut result = vec![];
+    for group in groups {
+        let mut map: HashMap<K, Vec<Candidate>> = HashMap::new();
+        for file in group {
+            if let Some(key) = key(&file) {
+                map.entry(key).or_default().push(file);
+            }
+        }
+        result.extend(map.into_values().filter(|group| group.len() > 1));
+    }
+    result
+}
+
+// 読めないファイルは警告を表示して候補から外す
+fn hash(path: &Path, limit: Option<u64>) -> Option<[u8; 32]> {
+    let result = File::open(path).and_then(|file| {
+        let mut hasher = Sha256::new();
+        io::copy(&mut file.take(limit.unwrap_or(u64::MAX)), &mut hasher)?;
+        Ok(hasher.finalize().into())
+    });
+    match result {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// 各グループの先頭のファイルを残し、残りをハードリンクに置き換えるか削除する
+pub fn print_groups(
+    groups: &[Vec<Candidate>],
+    dedupe: Option<Dedupe>,
+    dry_run: bool,
+) -> MyResult<bool> {
+    let mut stdout = io::stdout().lock();
+    let mut failed = false;
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(stdout)?;
+        }
+        for file in group {
+            stdout.write_all(file.path.as_os_str().as_encoded_bytes())?;
+            writeln!(stdout)?;
+        }
+        let Some(dedupe) = dedupe else {
+            continue;
+        };
+        let keep = &group[0].path;
+        for extra in &group[1..] {
+            let verb = match dedupe {
+                Dedupe::Hardlink => "link",
+                Dedupe::Delete => "delete",
+            };
+            if dry_run {
+                writeln!(stdout, "would {} {}", verb, extra.path.display())?;
+                continue;
+            }
+            let result = ensure_within(&extra.path, &extra.root, verb).and_then(|_| {
+                match dedupe {
+                    Dedupe::Hardlink => hardlink(keep, &extra.path),
+                    Dedupe::Delete => fs::remove_file(&extra.path),
+                }
+                .map_err(|e| format!("cannot {} {}: {}", verb, extra.path.display(), e).into())
+            });
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    Ok(!failed)
+}
+
+// 一時的な名前でリンクを作ってから置き換えるため、失敗しても元のファイルは残る
+fn hardlink(keep: &Path, extra: &Path) -> io::Result<()> {
+    let mut name = OsString::from(".");
+    name.push(extra.file_name().unwrap_or_default());
+    name.push(".findr-tmp");
+    let tmp = extra.with_file_name(name);
+    fs::hard_link(keep, &tmp)?;
+    fs::rename(&tmp, extra).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
@@ -0,0 +1,310 @@
+use crate::{EntryType, owner::NameCache, walk::Entry};
+use chrono::{DateTime, Local};
+use serde_json::json;
+use std::{fs::Metadata, iter::Peekable, os::unix::fs::MetadataExt, path::Path, str::Bytes};
+
+type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Directive {
+    Path,
+    Name,
+    Dir,
+    Size,
+    Mode,
+    Permissions,
+    User,
+    Group,
+    Time,
+    Epoch,
+    Depth,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(Vec<u8>),
+    Directive {
+        left: bool,
+        width: usize,
+        directive: Directive,
+    },
+}
+
+#[derive(Debug)]
+pub struct Format {
+    segments: Vec<Segment>,
+    names: NameCache,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> MyResult<Format> {
+        let invalid = || format!("Invalid --printf \"{}\"", format);
+        let mut segments = vec![];
+        let mut literal = vec![];
+        let mut bytes = format.bytes().peekable();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'\\' => match parse_escape(&mut bytes).ok_or_else(invalid)? {
+                    // find と同様に、"\c" 以降は出力しない
+                    None => break,
+                    Some(b) => literal.push(b),
+                },
+                b'%' if bytes.next_if_eq(&b'%').is_some() => literal.push(b'%'),
+                b'%' => {
+                    let segment = parse_directive(&mut bytes).ok_or_else(invalid)?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                }
+                _ => literal.push(b),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Format {
+            segments,
+            names: NameCache::default(),
+        })
+    }
+
+    pub fn render(&mut self, entry: &Entry) -> MyResult<Vec<u8>> {
+        let mut out = vec![];
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(bytes) => out.extend_from_slice(bytes),
+                Segment::Directive {
+                    left,
+                    width,
+                    directive,
+                } => {
+                    let value = value(*directive, entry, &mut self.names)?;
+                    pad(&mut out, &value, *left, *width);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+// 戻り値が Some(None) の場合は "\c"
+fn parse_escape(bytes: &mut Peekable<Bytes>) -> Option<Option<u8>> {
+    let b = match bytes.next()? {
+        b'a' => 0x07,
+        b'b' => 0x08,
+        b'f' => 0x0c,
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'v' => 0x0b,
+        b'\\' => b'\\',
+        b'c' => return Some(None),
+        d @ b'0'..=b'7' => {
+            let mut value = u32::from(d - b'0');
+            for _ in 0..2 {
+                match bytes.next_if(|b| matches!(b, b'0'..=b'7')) {
+                    Some(d) => value = value * 8 + u32::from(d - b'0'),
+                    None => break,
+                }
+            }
+            u8::try_from(value).ok()?
+        }
+        _ => return None,
+    };
+    Some(Some(b))
+}
+
+fn parse_directive(bytes: &mut Peekable<Bytes>) -> Option<Segment> {
+    let left = bytes.next_if_eq(&b'-').is_some();
+    let mut width = 0;
+    while let Some(d) = bytes.next_if(u8::is_ascii_digit) {
+        width = width * 10 + usize::from(d - b'0');
+    }
+    let directive = match bytes.next()? {
+        b'p' => Directive::Path,
+        b'f' => Directive::Name,
+        b'h' => Directive::Dir,
+        b's' => Directive::Size,
+        b'm' => Directive::Mode,
+        b'M' => Directive::Permissions,
+        b'u' => Directive::User,
+        b'g' => Directive::Group,
+        b't' => Directive::Time,
+        b'T' if bytes.next_if_eq(&b'@').is_some() => Directive::Epoch,
+        b'd' => Directive::Depth,
+        _ => return None,
+    };
+    Some(Segment::Directive {
+        left,
+        width,
+        directive,
+    })
+}
+
+fn value(directive: Directive, entry: &Entry, names: &mut NameCache) -> MyResult<Vec<u8>> {
+    let path = entry.path();
+    let value = match directive {
+        Directive::Path => path.as_os_str().as_encoded_bytes().to_vec(),
+        Directive::Name => entry.file_name().as_encoded_bytes().to_vec(),
+        Directive::Dir => parent(path).as_os_str().as_encoded_bytes().to_vec(),
+        Directive::Depth => entry.depth().to_string().into_bytes(),
+        _ => {
+            let metadata = metadata(entry)?;
+            match directive {
+                Directive::Size => metadata.len().to_string(),
+                Directive::Mode => format!("{:o}", metadata.mode() & 0o7777),
+                Directive::Permissions => permissions(metadata),
+                Directive::User => names.user(metadata.uid()).to_string(),
+                Directive::Group => names.group(metadata.gid()).to_string(),
+                Directive::Time => {
+                    let modified: DateTime<Local> = DateTime::from(metadata.modified()?);
+                    modified.format("%a %b %e %H:%M:%S %Y").to_string()
+                }
+                Directive::Epoch => format!("{}.{:09}", metadata.mtime(), metadata.mtime_nsec()),
+                _ => unreachable!("Invalid directive"),
+            }
+            .into_bytes()
+        }
+    };
+    Ok(value)
+}
+
+// 幅は文字数で数える。UTF-8 でない場合はバイト数で数える
+fn pad(out: &mut Vec<u8>, value: &[u8], left: bool, width: usize) {
+    let len = std::str::from_utf8(value).map_or(value.len(), |s| s.chars().count());
+    let fill = std::iter::repeat_n(b' ', width.saturating_sub(len));
+    if left {
+        out.extend_from_slice(value);
+        out.extend(fill);
+    } else {
+        out.extend(fill);
+        out.extend_from_slice(value);
+    }
+}
+
+// find と同様に、親ディレクトリがない場合は "." とする
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn metadata(entry: &Entry) -> MyResult<&Metadata> {
+    entry
+        .metadata()
+        .map_err(|e| format!("{}: {}", entry.path().display(), e).into())
+}
+
+// ls -l と同じ形式
+fn permissions(metadata: &Metadata) -> String {
+    let mode = metadata.mode();
+    let type_char = match EntryType::of(metadata.file_type()) {
+        Some(EntryType::Dir) => 'd',
+        Some(EntryType::Link) => 'l',
+        Some(EntryType::Socket) => 's',
+        Some(EntryType::Fifo) => 'p',
+        Some(EntryType::BlockDevice) => 'b',
+        Some(EntryType::CharDevice) => 'c',
+        _ => '-',
+    };
+    let mut result = String::from(type_char);
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        result.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        result.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        result.push(match (mode & special != 0, bits & 0o1 != 0) {
+            (true, true) => special_char,
+            (true, false) => special_char.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    result
+}
+
+// JSON の文字列は UTF-8 でなければならないため、パスは置き換え文字を含むことがある
+pub fn json(entry: &Entry, names: &mut NameCache) -> MyResult<String> {
+    let metadata = metadata(entry)?;
+    let entry_type = match EntryType::of(metadata.file_type()) {
+        Some(EntryType::Dir) => "directory",
+        Some(EntryType::File) => "file",
+        Some(EntryType::Link) => "symlink",
+        Some(EntryType::Socket) => "socket",
+        Some(EntryType::Fifo) => "fifo",
+        Some(EntryType::BlockDevice) => "block",
+        Some(EntryType::CharDevice) => "char",
+        None => "unknown",
+    };
+    let value = json!({
+        "path": entry.path().to_string_lossy(),
+        "name": entry.file_name().to_string_lossy(),
+        "depth": entry.depth(),
+        "type": entry_type,
+        "size": metadata.len(),
+        "mode": format!("{:04o}", metadata.mode() & 0o7777),
+        "permissions": permissions(metadata),
+        "uid": metadata.uid(),
+        "gid": metadata.gid(),
+        "user": names.user(metadata.uid()),
+        "group": names.group(metadata.gid()),
+        "mtime": metadata.mtime(),
+    });
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Directive, Format, Segment};
+
+    #[test]
+    fn test_parse() {
+        let format = Format::parse("%p\\t%-5s|%10f%%\\n").unwrap();
+        assert_eq!(
+            format.segments,
+            [
+                Segment::Directive {
+                    left: false,
+                    width: 0,
+                    directive: Directive::Path
+                },
+                Segment::Literal(b"\t".to_vec()),
+                Segment::Directive {
+                    left: true,
+                    width: 5,
+                    directive: Directive::Size
+                },
+                Segment::Literal(b"|".to_vec()),
+                Segment::Directive {
+                    left: false,
+                    width: 10,
+                    directive: Directive::Name
+                },
+                Segment::Literal(b"%\n".to_vec()),
+            ]
+        );
+
+        let format = Format::parse("a\\101\\0\\cb").unwrap();
+        assert_eq!(format.segments, [Segment::Literal(b"aA\0".to_vec())]);
+
+        let format = Format::parse("%T@").unwrap();
+        assert_eq!(
+            format.segments,
+            [Segment::Directive {
+                left: false,
+                width: 0,
+                directive: Directive::Epoch
+            }]
+        );
+
+        for bad in ["%", "%z", "%T", "%Tk", "\\", "\\q", "\\777", "%-"] {
+            let res = Format::parse(bad);
+            assert!(res.is_err(), "{}", bad);
+            assert_eq!(
+                res.unwrap_err().to_string(),
+                format!("Invalid --printf \"{}\"", bad)
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// ディレクトリごとの無視ルール。親ディレクトリのルールを辿って判定する
+#[derive(Debug)]
+pub struct IgnoreDir {
+    dir: PathBuf,
+    ignore: Option<Gitignore>,
+    gitignore: Option<Gitignore>,
+    exclude: Option<Gitignore>,
+    repo_root: bool,
+    in_repo: bool,
+    parent: Option<Arc<IgnoreDir>>,
+}
+
+impl IgnoreDir {
+    // 探索の起点。リポジトリのルートから起点までのルールを読み込む
+    pub fn root(dir: &Path) -> Arc<IgnoreDir> {
+        let ancestors: Vec<&Path> = dir.ancestors().collect();
+        let repo = ancestors.iter().position(|a| a.join(".git").exists());
+        let start = repo.unwrap_or(0);
+
+        let mut result: Option<Arc<IgnoreDir>> = None;
+        for ancestor in ancestors[..=start].iter().rev() {
+            let parent = result.take();
+            let in_repo = parent.as_ref().map_or(repo.is_some(), |p| p.in_repo);
+            result = Some(Arc::new(IgnoreDir::load(ancestor, parent, in_repo)));
+        }
+        result.expect("ancestors always include the directory itself")
+    }
+
+    pub fn child(self: &Arc<Self>, name: &OsStr) -> Arc<IgnoreDir> {
+        Arc::new(IgnoreDir::load(
+            &self.dir.join(name),
+            Some(self.clone()),
+            self.in_repo,
+        ))
+    }
+
+    fn load(dir: &Path, parent: Option<Arc<IgnoreDir>>, in_repo: bool) -> IgnoreDir {
+        let git_dir = dir.join(".git");
+        let repo_root = git_dir.exists();
+        let in_repo = in_repo || repo_root;
+        IgnoreDir {
+            dir: dir.to_path_buf(),
+            ignore: read(dir, &dir.join(".ignore")),
+            gitignore: if in_repo {
+                read(dir, &dir.join(".gitignore"))
+            } else {
+                None
+            },
+            exclude: if repo_root {
+                read(dir, &git_dir.join("info").join("exclude"))
+            } else {
+                None
+            },
+            repo_root,
+            in_repo,
+            parent,
+        }
+    }
+
+    fn chain(&self) -> impl Iterator<Item = &IgnoreDir> {
+        std::iter::successors(Some(self), |dir| dir.parent.as_deref())
+    }
+
+    // このディレクトリ直下の name を表示するかどうか
+    pub fn is_visible(&self, name: &OsStr, is_dir: bool, hidden: bool) -> bool {
+        if name == ".git" || (!hidden && name.as_encoded_bytes().starts_with(b".")) {
+            return false;
+        }
+        !self.is_ignored(&self.dir.join(name), is_dir)
+    }
+
+    // .ignore は .gitignore より、.gitignore は .git/info/exclude より優先する。
+    // 同じ種類のファイルでは深いディレクトリのものが優先される
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let ignores = self.chain().filter_map(|dir| dir.ignore.as_ref());
+        if let Some(ignored) = first_match(ignores, path, is_dir) {
+            return ignored;
+        }
+
+        let mut gitignores = vec![];
+        for dir in self.chain() {
+            gitignores.extend(dir.gitignore.as_ref());
+            if dir.repo_root {
+                gitignores.extend(dir.exclude.as_ref());
+                break;
+            }
+        }
+        first_match(gitignores.into_iter(), path, is_dir).unwrap_or(false)
+    }
+}
+
+fn first_match<'a, I>(matchers: I, path: &Path, is_dir: bool) -> Option<bool>
+where
+    I: Iterator<Item = &'a Gitignore>,
+{
+    matchers
+        .map(|m| m.matched(path, is_dir))
+        .find(|m| !m.is_none())
+        .map(|m| matches!(m, Match::Ignore(_)))
+}
+
+fn read(root: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(e) = builder.add(file) {
+        eprintln!("{}: {}", file.display(), e);
+    }
+    match builder.build() {
+        Ok(gitignore) => Some(gitignore),
+        Err(e) => {
+            eprintln!("{}: {}", root.display(), e);
+            None
+        }
+    }
+}
+
+// walkdir の filter_entry から呼び出す。深さごとにルールを積み上げる
+pub struct IgnoreFilter {
+    root: PathBuf,
+    hidden: bool,
+    stack: Vec<Arc<IgnoreDir>>,
+}
+
+impl IgnoreFilter {
+    pub fn new(root: &Path, hidden: bool) -> IgnoreFilter {
+        IgnoreFilter {
+            root: root.to_path_buf(),
+            hidden,
+            stack: vec![],
+        }
+    }
+
+    pub fn is_visible(&mut self, depth: usize, name: &OsStr, is_dir: bool) -> bool {
+        self.stack.truncate(depth);
+        if depth > 0 && !self.stack[depth - 1].is_visible(name, is_dir, self.hidden) {
+            return false;
+        }
+        if is_dir {
+            let dir = match self.stack.last() {
+                Some(parent) => parent.child(name),
+                None => IgnoreDir::root(&self.root),
+            };
+            self.stack.push(dir);
+        }
+        true
+    }
+}
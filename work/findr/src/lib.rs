@@ -1,7 +1,31 @@
+mod action;
+mod duplicates;
+mod format;
+mod gitignore;
+mod owner;
+mod perm;
+mod walk;
+
 use crate::EntryType::*;
-use clap::{App, Arg};
-use regex::Regex;
-use std::{error::Error, f32::consts::E, fs};
+pub use action::is_broken_pipe;
+use action::{Action, Exec};
+use clap::{App, Arg, ArgMatches};
+use duplicates::{Dedupe, Duplicates};
+use format::Format;
+use gitignore::IgnoreFilter;
+use globset::{GlobBuilder, GlobMatcher};
+use owner::{NameCache, OwnerCache};
+use perm::Perm;
+use regex::bytes::{Regex, RegexBuilder};
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs,
+    num::NonZeroUsize,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+use walk::Entry;
 use walkdir::WalkDir;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
@@ -11,63 +35,259 @@ enum EntryType {
     Dir,
     File,
     Link,
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+}
+
+impl EntryType {
+    fn of(file_type: fs::FileType) -> Option<EntryType> {
+        if file_type.is_dir() {
+            Some(Dir)
+        } else if file_type.is_file() {
+            Some(File)
+        } else if file_type.is_symlink() {
+            Some(Link)
+        } else if file_type.is_socket() {
+            Some(Socket)
+        } else if file_type.is_fifo() {
+            Some(Fifo)
+        } else if file_type.is_block_device() {
+            Some(BlockDevice)
+        } else if file_type.is_char_device() {
+            Some(CharDevice)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Config {
     paths: Vec<String>,
     names: Vec<Regex>,
+    inames: Vec<Regex>,
+    globs: Vec<GlobMatcher>,
+    path_globs: Vec<GlobMatcher>,
+    regexes: Vec<Regex>,
+    users: Vec<u32>,
+    groups: Vec<u32>,
+    no_user: bool,
+    no_group: bool,
+    perms: Vec<Perm>,
+    owners: OwnerCache,
     entry_types: Vec<EntryType>,
+    actions: Vec<Action>,
+    respect_ignore: bool,
+    hidden: bool,
+    threads: usize,
+    sort: bool,
+    follow_links: bool,
+    follow_roots: bool,
+    same_file_system: bool,
+    duplicates: bool,
+    dedupe: Option<Dedupe>,
+    dry_run: bool,
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let file_type_filter = |entry: &walkdir::DirEntry| {
-        if config.entry_types.is_empty() {
+impl Config {
+    fn is_match(&self, entry: &Entry) -> bool {
+        self.is_type_match(entry) && self.is_name_match(entry) && self.is_metadata_match(entry)
+    }
+
+    fn is_type_match(&self, entry: &Entry) -> bool {
+        if self.entry_types.is_empty() {
             return true;
         }
-        let entry_type = if entry.file_type().is_dir() {
-            Dir
-        } else if entry.file_type().is_file() {
-            File
-        } else if entry.file_type().is_symlink() {
-            Link
-        } else {
-            return false;
-        };
-        config.entry_types.contains(&entry_type)
-    };
+        EntryType::of(entry.file_type()).is_some_and(|t| self.entry_types.contains(&t))
+    }
 
-    let name_filter = |entry: &walkdir::DirEntry| {
-        if config.names.is_empty() {
+    fn is_metadata_match(&self, entry: &Entry) -> bool {
+        if self.users.is_empty()
+            && self.groups.is_empty()
+            && !self.no_user
+            && !self.no_group
+            && self.perms.is_empty()
+        {
             return true;
         }
-        config
-            .names
-            .iter()
-            .any(|name| name.is_match(entry.file_name().to_str().unwrap()))
-    };
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("{}: {}", entry.path().display(), e);
+                return false;
+            }
+        };
+        let (uid, gid) = (metadata.uid(), metadata.gid());
+        // find と異なり、同じ条件を複数指定した場合はいずれかに一致すればよい
+        any_or_empty(&self.users, |u| *u == uid)
+            && any_or_empty(&self.groups, |g| *g == gid)
+            && !(self.no_user && self.owners.has_user(uid))
+            && !(self.no_group && self.owners.has_group(gid))
+            && any_or_empty(&self.perms, |p| p.is_match(metadata.mode()))
+    }
+
+    // 名前やパスは UTF-8 とは限らないため、バイト列のまま比較する
+    fn is_name_match(&self, entry: &Entry) -> bool {
+        let name = entry.file_name();
+        let path = entry.path();
+        any_or_empty(&self.names, |re| re.is_match(name.as_encoded_bytes()))
+            && any_or_empty(&self.inames, |re| re.is_match(name.as_encoded_bytes()))
+            && any_or_empty(&self.globs, |glob| glob.is_match(name))
+            && any_or_empty(&self.path_globs, |glob| glob.is_match(path))
+            && any_or_empty(&self.regexes, |re| {
+                re.is_match(path.as_os_str().as_encoded_bytes())
+            })
+    }
+}
+
+fn any_or_empty<T>(tests: &[T], f: impl FnMut(&T) -> bool) -> bool {
+    tests.is_empty() || tests.iter().any(f)
+}
+
+pub fn run(mut config: Config) -> MyResult<()> {
+    let mut actions = std::mem::take(&mut config.actions);
+    let depth_first = actions.iter().any(|a| matches!(a, Action::Delete));
+    let mut failed = false;
+    let mut duplicates = config.duplicates.then(Duplicates::default);
+    // 出力先が閉じられたら探索をやめる。保留中の --exec は最後に実行する
+    let mut broken_pipe = None;
 
     for path in &config.paths {
-        WalkDir::new(path)
-            .into_iter()
-            .filter_map(|entry| match entry {
-                Err(e) => {
-                    eprintln!("{}", e);
-                    None
+        let root = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+
+        // 探索を続ける場合は true を返す
+        let mut process = |entry: &Entry| {
+            // 出力先が閉じられた後は、後回しにしたディレクトリも含めて何もしない
+            if broken_pipe.is_some() {
+                return false;
+            }
+            if let Some(duplicates) = duplicates.as_mut() {
+                duplicates.add(entry, &root);
+                return true;
+            }
+            for action in actions.iter_mut() {
+                match action.run(entry, &root) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) if is_broken_pipe(e.as_ref()) => {
+                        broken_pipe = Some(e);
+                        return false;
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        failed = true;
+                        break;
+                    }
                 }
-                Ok(entry) => Some(entry),
-            })
-            .filter(file_type_filter)
-            .filter(name_filter)
-            .for_each(|entry| {
-                println!("{}", entry.path().display());
-            });
+            }
+            true
+        };
+
+        // --delete ではディレクトリの中身を先に処理するため、ディレクトリを後回しにする
+        let mut deferred: Vec<Entry> = vec![];
+        let mut handle = |entry: Entry| {
+            if depth_first {
+                while let Some(dir) = deferred.pop_if(|dir| dir.depth() >= entry.depth()) {
+                    if !process(&dir) {
+                        return false;
+                    }
+                }
+                if entry.file_type().is_dir() {
+                    deferred.push(entry);
+                    return true;
+                }
+            }
+            process(&entry)
+        };
+
+        if config.threads > 1 {
+            // 並列探索では順序が不定になるため、必要なら集めてから並べ替える
+            if config.sort || depth_first {
+                let mut entries = vec![];
+                walk::parallel(&config, Path::new(path), &root, |e| {
+                    entries.push(e);
+                    true
+                });
+                entries.sort_by(|a, b| a.path().cmp(b.path()));
+                for entry in entries {
+                    if !handle(entry) {
+                        break;
+                    }
+                }
+            } else {
+                walk::parallel(&config, Path::new(path), &root, &mut handle);
+            }
+        } else {
+            let mut ignore_filter = config
+                .respect_ignore
+                .then(|| IgnoreFilter::new(&root, config.hidden));
+            let mut walker = WalkDir::new(path)
+                .follow_links(config.follow_links)
+                .follow_root_links(config.follow_roots)
+                .same_file_system(config.same_file_system);
+            if config.sort {
+                walker = walker.sort_by_file_name();
+            }
+            let entries = walker
+                .into_iter()
+                .filter_entry(|e| {
+                    ignore_filter.as_mut().is_none_or(|f| {
+                        f.is_visible(e.depth(), e.file_name(), e.file_type().is_dir())
+                    })
+                })
+                .filter_map(|entry| match entry {
+                    Err(e) => walk::broken_link(&e).or_else(|| {
+                        eprintln!("{}", e);
+                        None
+                    }),
+                    Ok(entry) if entry.depth() == 0 && config.follow_roots => {
+                        Some(Entry::from(entry).follow_root())
+                    }
+                    Ok(entry) => Some(Entry::from(entry)),
+                })
+                .filter(|entry| config.is_match(entry));
+            for entry in entries {
+                if !handle(entry) {
+                    break;
+                }
+            }
+        }
+
+        while let Some(dir) = deferred.pop() {
+            if !process(&dir) {
+                break;
+            }
+        }
+        if broken_pipe.is_some() {
+            break;
+        }
+    }
+
+    if let Some(duplicates) = duplicates {
+        let groups = duplicates.groups();
+        if !duplicates::print_groups(&groups, config.dedupe, config.dry_run)? {
+            failed = true;
+        }
+    }
+
+    for action in actions.iter_mut() {
+        if let Err(e) = action.finish() {
+            eprintln!("{}", e);
+            failed = true;
+        }
+    }
+
+    if failed {
+        return Err("one or more actions failed".into());
     }
-    Ok(())
+    broken_pipe.map_or(Ok(()), Err)
 }
 
 pub fn get_args() -> MyResult<Config> {
-    let matches = App::new("uniqr")
+    let args = terminate_exec_batches(std::env::args_os());
+    let matches = App::new("findr")
         .version("0.1.0")
         .author("John Doe")
         .about("Rust find")
@@ -87,6 +307,38 @@ pub fn get_args() -> MyResult<Config> {
                 .multiple(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("inames")
+                .value_name("NAME")
+                .long("iname")
+                .help("Name, ignoring case")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("globs")
+                .value_name("GLOB")
+                .long("glob")
+                .help("Shell glob matched against the name")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("path_globs")
+                .value_name("GLOB")
+                .long("path")
+                .help("Shell glob matched against the full path")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("regexes")
+                .value_name("REGEX")
+                .long("regex")
+                .help("Regex matched against the whole path")
+                .multiple(true)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("entry_types")
                 .value_name("TYPE")
@@ -95,21 +347,209 @@ pub fn get_args() -> MyResult<Config> {
                 .help("Entry type")
                 .multiple(true)
                 .takes_value(true)
-                .possible_values(&["f", "d", "l"]),
+                .possible_values(&["f", "d", "l", "s", "p", "b", "c"]),
+        )
+        .arg(
+            Arg::with_name("users")
+                .value_name("USER")
+                .long("user")
+                .help("Owned by user name or UID; repeat to match any of them")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("groups")
+                .value_name("GROUP")
+                .long("group")
+                .help("Owned by group name or GID; repeat to match any of them")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("no_user")
+                .long("nouser")
+                .help("Owned by a UID with no user"),
+        )
+        .arg(
+            Arg::with_name("no_group")
+                .long("nogroup")
+                .help("Owned by a GID with no group"),
+        )
+        .arg(
+            Arg::with_name("perms")
+                .value_name("MODE")
+                .long("perm")
+                .help(
+                    "Permission bits: MODE exactly, -MODE all of, /MODE any of; \
+                     repeat to match any of them",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("print")
+                .long("print")
+                .help("Print the path followed by a newline"),
+        )
+        .arg(
+            Arg::with_name("print0")
+                .long("print0")
+                .help("Print the path followed by a NUL character"),
+        )
+        .arg(
+            Arg::with_name("printf")
+                .value_name("FORMAT")
+                .long("printf")
+                .help("Print FORMAT with %p %f %h %s %m %M %u %g %t %T@ %d directives")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print the metadata of each entry as a JSON line"),
+        )
+        .arg(
+            Arg::with_name("delete")
+                .long("delete")
+                .help("Delete files and directories, depth-first"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .value_name("N")
+                .long("threads")
+                .help("Number of threads used to walk directories")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .help("Walk directories in file name order"),
+        )
+        .arg(
+            Arg::with_name("follow_links")
+                .short("L")
+                .help("Follow symbolic links")
+                .overrides_with("follow_roots"),
+        )
+        .arg(
+            Arg::with_name("follow_roots")
+                .short("H")
+                .help("Follow symbolic links given as search paths")
+                .overrides_with("follow_links"),
+        )
+        .arg(
+            Arg::with_name("same_file_system")
+                .long("xdev")
+                .help("Don't descend into directories on other file systems"),
+        )
+        .arg(
+            Arg::with_name("respect_ignore")
+                .long("respect-ignore")
+                .help("Skip entries matched by .gitignore, .ignore and .git/info/exclude"),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .requires("respect_ignore")
+                .help("Include hidden entries with --respect-ignore"),
+        )
+        .arg(
+            Arg::with_name("duplicates")
+                .long("duplicates")
+                .help("Print groups of files with identical contents")
+                .conflicts_with_all(&[
+                    "print", "print0", "printf", "json", "delete", "exec", "execdir", "ok",
+                ]),
+        )
+        .arg(
+            Arg::with_name("dedupe")
+                .value_name("ACTION")
+                .long("dedupe")
+                .help(
+                    "Replace all but the first file of each group with a hard link, or delete them",
+                )
+                .takes_value(true)
+                .possible_values(&["hardlink", "delete"])
+                .requires("duplicates"),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .help("Show what --dedupe would do without changing anything")
+                .requires("dedupe"),
+        )
+        .arg(exec_arg(
+            "exec",
+            "Run COMMAND for each entry, ending with ';' or '{} +'",
+        ))
+        .arg(exec_arg(
+            "execdir",
+            "Like --exec, but run COMMAND in the directory of each entry",
+        ))
+        .arg(exec_arg(
+            "ok",
+            "Like --exec, but ask for confirmation first",
+        ))
+        .get_matches_from(args);
+
+    let mut actions = vec![];
+    for name in ["print", "print0", "json", "delete"] {
+        for index in matches.indices_of(name).into_iter().flatten() {
+            let action = match name {
+                "print" => Action::Print,
+                "print0" => Action::Print0,
+                "json" => Action::Json(NameCache::default()),
+                _ => Action::Delete,
+            };
+            actions.push((index, action));
+        }
+    }
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("printf"), matches.values_of("printf"))
+    {
+        for (index, value) in indices.zip(values) {
+            actions.push((index, Action::Printf(Format::parse(value)?)));
+        }
+    }
+    for name in ["exec", "execdir", "ok"] {
+        let flag = format!("--{}", name);
+        for (index, argv) in exec_groups(&matches, name) {
+            let exec = Exec::new(&flag, argv, name == "execdir", name == "ok")?;
+            actions.push((index, Action::Exec(exec)));
+        }
+    }
+    actions.sort_by_key(|(index, _)| *index);
+    let mut actions: Vec<_> = actions.into_iter().map(|(_, action)| action).collect();
+    if actions.is_empty() {
+        actions.push(Action::Print);
+    }
+
+    let threads = matches.value_of("threads").unwrap();
+    let threads = threads
+        .parse::<NonZeroUsize>()
+        .map_err(|_| format!("Invalid --threads \"{}\"", threads))?;
 
     Ok(Config {
         paths: matches.values_of_lossy("paths").unwrap(),
-        names: matches
-            .values_of_lossy("names")
-            .map(|v| {
-                v.into_iter()
-                    .map(|s| Regex::new(&s).map_err(|_| format!("Invalid --name \"{}\"", s)))
-                    .collect::<Result<Vec<_>, _>>()
-            })
-            .transpose()?
-            .unwrap_or_default(),
+        names: parse_regexes(&matches, "names", "--name", false, false)?,
+        inames: parse_regexes(&matches, "inames", "--iname", true, false)?,
+        globs: parse_globs(&matches, "globs", "--glob")?,
+        path_globs: parse_globs(&matches, "path_globs", "--path")?,
+        regexes: parse_regexes(&matches, "regexes", "--regex", false, true)?,
+        users: parse_values(&matches, "users", owner::parse_user)?,
+        groups: parse_values(&matches, "groups", owner::parse_group)?,
+        no_user: matches.is_present("no_user"),
+        no_group: matches.is_present("no_group"),
+        perms: parse_values(&matches, "perms", Perm::parse)?,
+        owners: OwnerCache::default(),
         entry_types: matches
             .values_of_lossy("entry_types")
             .map(|values| {
@@ -119,10 +559,135 @@ pub fn get_args() -> MyResult<Config> {
                         "f" => File,
                         "d" => Dir,
                         "l" => Link,
+                        "s" => Socket,
+                        "p" => Fifo,
+                        "b" => BlockDevice,
+                        "c" => CharDevice,
                         _ => unreachable!("Invalid type"),
                     })
                     .collect::<Vec<_>>()
             })
             .unwrap_or_default(),
+        actions,
+        respect_ignore: matches.is_present("respect_ignore"),
+        hidden: matches.is_present("hidden"),
+        threads: threads.into(),
+        sort: matches.is_present("sort"),
+        follow_links: matches.is_present("follow_links"),
+        follow_roots: matches.is_present("follow_links") || matches.is_present("follow_roots"),
+        same_file_system: matches.is_present("same_file_system"),
+        duplicates: matches.is_present("duplicates"),
+        dedupe: matches.value_of("dedupe").map(|v| match v {
+            "hardlink" => Dedupe::Hardlink,
+            _ => Dedupe::Delete,
+        }),
+        dry_run: matches.is_present("dry_run"),
     })
 }
+
+fn parse_values<T>(
+    matches: &ArgMatches,
+    name: &str,
+    parse: fn(&str) -> MyResult<T>,
+) -> MyResult<Vec<T>> {
+    matches
+        .values_of(name)
+        .into_iter()
+        .flatten()
+        .map(parse)
+        .collect()
+}
+
+// find の -regex と同様に、anchored の場合はパス全体に一致させる
+fn parse_regexes(
+    matches: &ArgMatches,
+    name: &str,
+    flag: &str,
+    case_insensitive: bool,
+    anchored: bool,
+) -> MyResult<Vec<Regex>> {
+    matches
+        .values_of_lossy(name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| {
+            let pattern = if anchored {
+                format!("^(?:{})$", s)
+            } else {
+                s.clone()
+            };
+            RegexBuilder::new(&pattern)
+                .case_insensitive(case_insensitive)
+                .build()
+                .map_err(|_| format!("Invalid {} \"{}\"", flag, s).into())
+        })
+        .collect()
+}
+
+// find と同様に、"*" はパス区切り文字にも一致する
+fn parse_globs(matches: &ArgMatches, name: &str, flag: &str) -> MyResult<Vec<GlobMatcher>> {
+    matches
+        .values_of_lossy(name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| {
+            GlobBuilder::new(&s)
+                .literal_separator(false)
+                .backslash_escape(true)
+                .build()
+                .map(|glob| glob.compile_matcher())
+                .map_err(|_| format!("Invalid {} \"{}\"", flag, s).into())
+        })
+        .collect()
+}
+
+fn exec_arg<'a, 'b>(name: &'a str, help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .value_name("COMMAND")
+        .long(name)
+        .help(help)
+        .takes_value(true)
+        .multiple(true)
+        .allow_hyphen_values(true)
+        .value_terminator(";")
+}
+
+// clap は終端文字を一つしか指定できないため、"{} +" の後ろに ";" を補う
+fn terminate_exec_batches<I: IntoIterator<Item = OsString>>(args: I) -> Vec<OsString> {
+    let mut result: Vec<OsString> = vec![];
+    let mut in_exec = false;
+    for arg in args {
+        if in_exec {
+            if arg == ";" {
+                in_exec = false;
+            } else if arg == "+" && result.last().is_some_and(|prev| prev == "{}") {
+                result.push(arg);
+                result.push(";".into());
+                in_exec = false;
+                continue;
+            }
+        } else if arg == "--exec" || arg == "--execdir" || arg == "--ok" {
+            in_exec = true;
+        }
+        result.push(arg);
+    }
+    result
+}
+
+// 複数回指定された --exec の値を、出現ごとに分けて取り出す
+fn exec_groups(matches: &ArgMatches, name: &str) -> Vec<(usize, Vec<String>)> {
+    let (Some(indices), Some(values)) = (matches.indices_of(name), matches.values_of_lossy(name))
+    else {
+        return vec![];
+    };
+    let mut groups: Vec<(usize, Vec<String>)> = vec![];
+    let mut prev = None;
+    for (index, value) in indices.zip(values) {
+        match groups.last_mut() {
+            Some((_, group)) if prev == Some(index - 1) => group.push(value),
+            _ => groups.push((index, vec![value])),
+        }
+        prev = Some(index);
+    }
+    groups
+}
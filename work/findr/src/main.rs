@@ -1,5 +1,9 @@
 fn main() {
     if let Err(e) = findr::get_args().and_then(findr::run) {
+        // 出力先が閉じられた場合は、正常に終了する
+        if findr::is_broken_pipe(e.as_ref()) {
+            return;
+        }
         eprintln!("{}", e);
         std::process::exit(1);
     }
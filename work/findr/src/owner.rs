@@ -0,0 +1,68 @@
+use std::{collections::HashMap, sync::Mutex};
+use users::{get_group_by_gid, get_group_by_name, get_user_by_name, get_user_by_uid};
+
+type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+pub fn parse_user(value: &str) -> MyResult<u32> {
+    get_user_by_name(value)
+        .map(|u| u.uid())
+        .or_else(|| value.parse().ok())
+        .ok_or_else(|| format!("Invalid --user \"{}\"", value).into())
+}
+
+pub fn parse_group(value: &str) -> MyResult<u32> {
+    get_group_by_name(value)
+        .map(|g| g.gid())
+        .or_else(|| value.parse().ok())
+        .ok_or_else(|| format!("Invalid --group \"{}\"", value).into())
+}
+
+// --nouser, --nogroup ではエントリごとに問い合わせるため、結果を覚えておく
+#[derive(Debug, Default)]
+pub struct OwnerCache {
+    users: Mutex<HashMap<u32, bool>>,
+    groups: Mutex<HashMap<u32, bool>>,
+}
+
+impl OwnerCache {
+    pub fn has_user(&self, uid: u32) -> bool {
+        *self
+            .users
+            .lock()
+            .unwrap()
+            .entry(uid)
+            .or_insert_with(|| get_user_by_uid(uid).is_some())
+    }
+
+    pub fn has_group(&self, gid: u32) -> bool {
+        *self
+            .groups
+            .lock()
+            .unwrap()
+            .entry(gid)
+            .or_insert_with(|| get_group_by_gid(gid).is_some())
+    }
+}
+
+// --printf や --json で表示する名前。見つからない場合は数値をそのまま使う
+#[derive(Debug, Default)]
+pub struct NameCache {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl NameCache {
+    pub fn user(&mut self, uid: u32) -> &str {
+        self.users.entry(uid).or_insert_with(|| {
+            get_user_by_uid(uid)
+                .map_or_else(|| uid.to_string(), |u| u.name().to_string_lossy().into())
+        })
+    }
+
+    pub fn group(&mut self, gid: u32) -> &str {
+        self.groups.entry(gid).or_insert_with(|| {
+            get_group_by_gid(gid)
+                .map_or_else(|| gid.to_string(), |g| g.name().to_string_lossy().into())
+        })
+    }
+}
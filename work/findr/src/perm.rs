@@ -0,0 +1,120 @@
+type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Perm {
+    Exact(u32),
+    All(u32),
+    Any(u32),
+}
+
+impl Perm {
+    pub fn parse(value: &str) -> MyResult<Perm> {
+        let (make, mode): (fn(u32) -> Perm, &str) = match value.as_bytes().first() {
+            Some(b'-') => (Perm::All, &value[1..]),
+            Some(b'/') => (Perm::Any, &value[1..]),
+            _ => (Perm::Exact, value),
+        };
+        parse_mode(mode)
+            .map(make)
+            .ok_or_else(|| format!("Invalid --perm \"{}\"", value).into())
+    }
+
+    pub fn is_match(&self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match *self {
+            Perm::Exact(perm) => mode == perm,
+            Perm::All(perm) => mode & perm == perm,
+            // find と同様に、"/000" はすべてに一致する
+            Perm::Any(perm) => perm == 0 || mode & perm != 0,
+        }
+    }
+}
+
+fn parse_mode(mode: &str) -> Option<u32> {
+    if !mode.is_empty() && mode.bytes().all(|b| b.is_ascii_digit()) {
+        return u32::from_str_radix(mode, 8).ok().filter(|m| *m <= 0o7777);
+    }
+    mode.split(',')
+        .try_fold(0, |acc, clause| parse_clause(clause, acc))
+}
+
+// "u+w" や "go=rx" のような chmod 形式の指定を、モード 0 に適用した結果を返す
+fn parse_clause(clause: &str, mode: u32) -> Option<u32> {
+    let op_pos = clause.find(['+', '-', '='])?;
+    let (who, rest) = clause.split_at(op_pos);
+
+    let mut mask = 0;
+    for c in who.chars() {
+        mask |= match c {
+            'u' => 0o4700,
+            'g' => 0o2070,
+            'o' => 0o1007,
+            'a' => 0o7777,
+            _ => return None,
+        };
+    }
+    if mask == 0 {
+        mask = 0o7777;
+    }
+
+    let mut mode = mode;
+    let mut chars = rest.chars().peekable();
+    while let Some(op) = chars.next() {
+        let mut bits = 0;
+        while let Some(c) = chars.next_if(|c| !matches!(c, '+' | '-' | '=')) {
+            bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => return None,
+            };
+        }
+        let bits = bits & mask;
+        match op {
+            '+' => mode |= bits,
+            '-' => mode &= !bits,
+            _ => mode = (mode & !mask) | bits,
+        }
+    }
+    Some(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Perm;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Perm::parse("644").unwrap(), Perm::Exact(0o644));
+        assert_eq!(Perm::parse("-022").unwrap(), Perm::All(0o022));
+        assert_eq!(Perm::parse("/4000").unwrap(), Perm::Any(0o4000));
+        assert_eq!(Perm::parse("u=rw,go=r").unwrap(), Perm::Exact(0o644));
+        assert_eq!(Perm::parse("-o+w").unwrap(), Perm::All(0o002));
+        assert_eq!(Perm::parse("/u=s").unwrap(), Perm::Any(0o4000));
+        assert_eq!(Perm::parse("a+x").unwrap(), Perm::Exact(0o111));
+        assert_eq!(Perm::parse("+t").unwrap(), Perm::Exact(0o1000));
+        assert_eq!(Perm::parse("u=rwx,u-w").unwrap(), Perm::Exact(0o500));
+
+        for bad in ["", "-", "8", "77777", "u", "q+w", "u+z", "u+w,"] {
+            let res = Perm::parse(bad);
+            assert!(res.is_err(), "{}", bad);
+            assert_eq!(
+                res.unwrap_err().to_string(),
+                format!("Invalid --perm \"{}\"", bad)
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_match() {
+        assert!(Perm::Exact(0o644).is_match(0o100644));
+        assert!(!Perm::Exact(0o644).is_match(0o100664));
+        assert!(Perm::All(0o022).is_match(0o777));
+        assert!(!Perm::All(0o022).is_match(0o755));
+        assert!(Perm::Any(0o022).is_match(0o664));
+        assert!(!Perm::Any(0o022).is_match(0o644));
+        assert!(Perm::Any(0).is_match(0o000));
+    }
+}
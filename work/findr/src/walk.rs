@@ -0,0 +1,364 @@
+use crate::{Config, gitignore::IgnoreDir};
+use crossbeam_deque::{Injector, Stealer, Worker};
+use crossbeam_utils::Backoff;
+use std::{
+    ffi::OsStr,
+    fs::{self, FileType, Metadata},
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+#[derive(Debug)]
+pub struct Entry {
+    path: PathBuf,
+    depth: usize,
+    file_type: FileType,
+    is_symlink: bool,
+    metadata: OnceLock<Metadata>,
+}
+
+impl Entry {
+    fn new(path: PathBuf, depth: usize, file_type: FileType, is_symlink: bool) -> Entry {
+        Entry {
+            path,
+            depth,
+            file_type,
+            is_symlink,
+            metadata: OnceLock::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn path_is_symlink(&self) -> bool {
+        self.is_symlink
+    }
+
+    // 複数の条件で使われるため、一度だけ取得する
+    pub fn metadata(&self) -> io::Result<&Metadata> {
+        if let Some(metadata) = self.metadata.get() {
+            return Ok(metadata);
+        }
+        // 辿ったシンボリックリンクはリンク先の情報を返す
+        let metadata = if self.is_symlink && !self.file_type.is_symlink() {
+            fs::metadata(&self.path)?
+        } else {
+            fs::symlink_metadata(&self.path)?
+        };
+        Ok(self.metadata.get_or_init(|| metadata))
+    }
+}
+
+impl From<walkdir::DirEntry> for Entry {
+    fn from(entry: walkdir::DirEntry) -> Self {
+        Entry::new(
+            entry.path().to_path_buf(),
+            entry.depth(),
+            entry.file_type(),
+            entry.path_is_symlink(),
+        )
+    }
+}
+
+impl Entry {
+    // walkdir は -H で辿った起点のリンクをリンクのまま返すため、リンク先の種類にする。
+    // リンク先が存在しない場合は、find と同様にリンクそのものとして扱う
+    pub fn follow_root(self) -> Entry {
+        if !self.file_type.is_symlink() {
+            return self;
+        }
+        match fs::metadata(&self.path) {
+            Ok(metadata) => Entry {
+                file_type: metadata.file_type(),
+                metadata: OnceLock::from(metadata),
+                ..self
+            },
+            Err(_) => self,
+        }
+    }
+}
+
+// walkdir がリンク先を辿れなかった場合に、リンクそのものをエントリとして返す
+pub fn broken_link(e: &walkdir::Error) -> Option<Entry> {
+    if e.loop_ancestor().is_some() {
+        return None;
+    }
+    let path = e.path()?;
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if !metadata.is_symlink() || fs::metadata(path).is_ok() {
+        return None;
+    }
+    Some(Entry::new(
+        path.to_path_buf(),
+        e.depth(),
+        metadata.file_type(),
+        true,
+    ))
+}
+
+struct Job {
+    path: PathBuf,
+    depth: usize,
+    ignore: Option<Arc<IgnoreDir>>,
+    ancestor: Option<Arc<Ancestor>>,
+}
+
+// シンボリックリンクを辿るときのループ検出に使う
+struct Ancestor {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+    parent: Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn find(self: &Arc<Self>, metadata: &Metadata) -> Option<&Ancestor> {
+        std::iter::successors(Some(self.as_ref()), |a| a.parent.as_deref())
+            .find(|a| a.dev == metadata.dev() && a.ino == metadata.ino())
+    }
+}
+
+struct Shared<'a> {
+    config: &'a Config,
+    injector: Injector<Job>,
+    stealers: Vec<Stealer<Job>>,
+    pending: AtomicUsize,
+    root_dev: u64,
+}
+
+// ディレクトリの読み込みを複数スレッドで行い、条件に一致したエントリを呼び出し元のスレッドに渡す。
+// handle が false を返したら探索をやめる
+pub fn parallel<F: FnMut(Entry) -> bool>(
+    config: &Config,
+    start: &Path,
+    root: &Path,
+    mut handle: F,
+) {
+    let metadata = match fs::symlink_metadata(start) {
+        Ok(metadata) if metadata.is_symlink() && config.follow_roots => {
+            fs::metadata(start).or(Ok(metadata))
+        }
+        result => result,
+    };
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("{}", io_error(start, e));
+            return;
+        }
+    };
+    let is_symlink = fs::symlink_metadata(start).is_ok_and(|m| m.is_symlink());
+
+    let workers: Vec<_> = (0..config.threads).map(|_| Worker::new_lifo()).collect();
+    let shared = Shared {
+        config,
+        injector: Injector::new(),
+        stealers: workers.iter().map(Worker::stealer).collect(),
+        pending: AtomicUsize::new(0),
+        root_dev: metadata.dev(),
+    };
+
+    if metadata.is_dir() {
+        shared.pending.store(1, Ordering::SeqCst);
+        shared.injector.push(Job {
+            path: start.to_path_buf(),
+            depth: 0,
+            ignore: config.respect_ignore.then(|| IgnoreDir::root(root)),
+            ancestor: config.follow_links.then(|| {
+                Arc::new(Ancestor {
+                    path: start.to_path_buf(),
+                    dev: metadata.dev(),
+                    ino: metadata.ino(),
+                    parent: None,
+                })
+            }),
+        });
+    }
+    let entry = Entry::new(start.to_path_buf(), 0, metadata.file_type(), is_symlink);
+
+    let (tx, rx) = mpsc::channel();
+    if config.is_match(&entry) {
+        tx.send(entry).expect("receiver is alive");
+    }
+
+    thread::scope(|s| {
+        for worker in workers {
+            let tx = tx.clone();
+            let shared = &shared;
+            s.spawn(move || work(shared, worker, tx));
+        }
+        drop(tx);
+
+        for entry in rx {
+            if !handle(entry) {
+                break;
+            }
+        }
+    });
+}
+
+// 仕事がない間は徐々に待ち方を緩め、最後は眠って CPU を空ける。
+// ネットワーク越しのファイルシステムでは、ほとんどの時間を readdir の待ちに使う
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+fn work(shared: &Shared, local: Worker<Job>, tx: Sender<Entry>) {
+    let backoff = Backoff::new();
+    loop {
+        match local
+            .pop()
+            .or_else(|| steal(&local, &shared.injector, &shared.stealers))
+        {
+            Some(job) => {
+                backoff.reset();
+                for child in read_dir(shared, &job, &tx) {
+                    shared.pending.fetch_add(1, Ordering::SeqCst);
+                    local.push(child);
+                }
+                shared.pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            None if shared.pending.load(Ordering::SeqCst) == 0 => break,
+            None if backoff.is_completed() => thread::sleep(IDLE_SLEEP),
+            None => backoff.snooze(),
+        }
+    }
+}
+
+fn steal<T>(local: &Worker<T>, injector: &Injector<T>, stealers: &[Stealer<T>]) -> Option<T> {
+    std::iter::repeat_with(|| {
+        injector
+            .steal_batch_and_pop(local)
+            .or_else(|| stealers.iter().map(|s| s.steal()).collect())
+    })
+    .find(|s| !s.is_retry())
+    .and_then(|s| s.success())
+}
+
+fn read_dir(shared: &Shared, job: &Job, tx: &Sender<Entry>) -> Vec<Job> {
+    let config = shared.config;
+    let entries = match fs::read_dir(&job.path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", io_error(&job.path, e));
+            return vec![];
+        }
+    };
+
+    let mut children = vec![];
+    for entry in entries {
+        let (path, file_type) = match entry.and_then(|e| Ok((e.path(), e.file_type()?))) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("{}", io_error(&job.path, e));
+                continue;
+            }
+        };
+        let is_symlink = file_type.is_symlink();
+        let follow = is_symlink && config.follow_links;
+        // ループ検出や --xdev では、ディレクトリの dev と inode が必要になる
+        let metadata =
+            if follow || file_type.is_dir() && (config.follow_links || config.same_file_system) {
+                // リンク先が存在しない場合は、find と同様にリンクそのものとして扱う
+                let result = if follow {
+                    fs::metadata(&path).or_else(|_| fs::symlink_metadata(&path))
+                } else {
+                    fs::symlink_metadata(&path)
+                };
+                match result {
+                    Ok(metadata) => Some(metadata),
+                    Err(e) => {
+                        eprintln!("{}", io_error(&path, e));
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+        let file_type = metadata.as_ref().map_or(file_type, Metadata::file_type);
+        let entry = Entry::new(path, job.depth + 1, file_type, is_symlink);
+        if let Some(ignore) = &job.ignore
+            && !ignore.is_visible(entry.file_name(), file_type.is_dir(), config.hidden)
+        {
+            continue;
+        }
+        if file_type.is_dir() {
+            match descend(shared, job, &entry, metadata.as_ref()) {
+                Ok(Some(child)) => children.push(child),
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            }
+        }
+        // 呼び出し元が探索をやめた場合は、これ以上辿らない
+        if config.is_match(&entry) && tx.send(entry).is_err() {
+            return vec![];
+        }
+    }
+    children
+}
+
+fn descend(
+    shared: &Shared,
+    job: &Job,
+    entry: &Entry,
+    metadata: Option<&Metadata>,
+) -> Result<Option<Job>, String> {
+    let config = shared.config;
+    let ancestor = match (&job.ancestor, metadata) {
+        (Some(parent), Some(metadata)) => {
+            if entry.is_symlink
+                && let Some(ancestor) = parent.find(metadata)
+            {
+                return Err(format!(
+                    "File system loop found: {} points to an ancestor {}",
+                    entry.path.display(),
+                    ancestor.path.display()
+                ));
+            }
+            Some(Arc::new(Ancestor {
+                path: entry.path.clone(),
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+                parent: Some(parent.clone()),
+            }))
+        }
+        _ => None,
+    };
+    if config.same_file_system && metadata.is_some_and(|m| m.dev() != shared.root_dev) {
+        return Ok(None);
+    }
+    Ok(Some(Job {
+        path: entry.path.clone(),
+        depth: entry.depth,
+        ignore: job.ignore.as_ref().map(|i| i.child(entry.file_name())),
+        ancestor,
+    }))
+}
+
+// walkdir と同じ形式でエラーを表示する
+fn io_error(path: &Path, e: std::io::Error) -> String {
+    format!("IO error for operation on {}: {}", path.display(), e)
+}
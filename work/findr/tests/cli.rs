@@ -39,7 +39,7 @@ fn skips_bad_dir() -> TestResult {
 #[test]
 fn dies_bad_name() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["--name", "*.csv"])
+        .args(["--name", "*.csv"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Invalid --name \"*.csv\""));
@@ -51,7 +51,7 @@ fn dies_bad_name() -> TestResult {
 fn dies_bad_type() -> TestResult {
     let expected = "error: 'x' isn't a valid value for '--type <TYPE>...'";
     Command::cargo_bin(PRG)?
-        .args(&["--type", "x"])
+        .args(["--type", "x"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -67,7 +67,7 @@ fn format_file_name(expected_file: &str) -> Cow<str> {
 
 // --------------------------------------------------
 #[cfg(not(windows))]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Borrowed(expected_file)
     expected_file.into()
 }
@@ -293,7 +293,7 @@ fn unreadable_dir() -> TestResult {
     //permissions.set_mode(0o000);
 
     std::process::Command::new("chmod")
-        .args(&["000", dirname])
+        .args(["000", dirname])
         .status()
         .expect("failed");
 
@@ -313,3 +313,932 @@ fn unreadable_dir() -> TestResult {
     assert!(stderr.contains("cant-touch-this: Permission denied"));
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn print0() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/b", "--print0"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/b\0tests/inputs/a/b/c\0tests/inputs/a/b/c/c.mp3\0tests/inputs/a/b/b.csv\0".to_string())
+        .stdout(predicate::str::contains('\n').not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exec() -> TestResult {
+    run(
+        &["tests/inputs", "-t", "f", "--exec", "echo", "{}", ";"],
+        "tests/expected/type_f.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn exec_batch() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a",
+            "-t",
+            "f",
+            "--exec",
+            "echo",
+            "files:",
+            "{}",
+            "+",
+        ])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let mut files: Vec<&str> = lines[0].split(' ').collect();
+    assert_eq!(files.remove(0), "files:");
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "tests/inputs/a/a.txt",
+            "tests/inputs/a/b/b.csv",
+            "tests/inputs/a/b/c/c.mp3"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exec_filters_later_actions() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--exec", "false", ";", "--print"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn exec_batch_failure() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "--exec", "false", "{}", "+"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec_batch_after_broken_pipe() -> TestResult {
+    // パイプの容量を超える量を出力させる
+    let dir = tempfile::tempdir()?;
+    for i in 0..2000 {
+        fs::write(dir.path().join(format!("{:0>40}", i)), "")?;
+    }
+    let marker = dir.path().join("marker");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .arg(dir.path())
+        .args(["-t", "f", "--exec", "sh", "-c"])
+        .arg(format!("touch '{}'", marker.display()))
+        .args(["{}", "+", "--print"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    drop(child.stdout.take());
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+    assert!(marker.exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn execdir() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a/b",
+            "-t",
+            "f",
+            "--execdir",
+            "echo",
+            "{}",
+            ";",
+        ])
+        .assert()
+        .success()
+        .stdout("./c.mp3\n./b.csv\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ok_confirm() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "-t", "f", "--ok", "echo", "{}", ";"])
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout("tests/inputs/f/f.txt\n")
+        .stderr("< echo tests/inputs/f/f.txt > ? ");
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "-t", "f", "--ok", "echo", "{}", ";"])
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_ok_batch() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--ok", "echo", "{}", "+"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--ok does not support \"{} +\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete() -> TestResult {
+    let dir = make_tree(&[
+        ("root/keep/k.txt", ""),
+        ("root/x/y/z.csv", ""),
+        ("root/x/a.csv", ""),
+    ])?;
+    let root = dir.path().join("root");
+
+    Command::cargo_bin(PRG)?
+        .arg(root.join("x"))
+        .arg("--delete")
+        .assert()
+        .success()
+        .stdout("");
+    assert!(!root.join("x").exists());
+    assert!(root.join("keep/k.txt").exists());
+
+    Command::cargo_bin(PRG)?
+        .arg(&root)
+        .args(["-n", "[.]txt$", "--delete"])
+        .assert()
+        .success();
+    assert!(!root.join("keep/k.txt").exists());
+    assert!(root.join("keep").exists());
+
+    // 開始パスの . は、エラーにせずに残す
+    fs::write(root.join("keep/k.txt"), "")?;
+    Command::cargo_bin(PRG)?
+        .current_dir(root.join("keep"))
+        .args([".", "--delete"])
+        .assert()
+        .success()
+        .stderr("");
+    assert!(!root.join("keep/k.txt").exists());
+    assert!(root.join("keep").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn delete_after_broken_pipe() -> TestResult {
+    let dir = make_tree(&[("root/a.txt", ""), ("root/sub/b.txt", "")])?;
+    let root = dir.path().join("root");
+
+    // 最初の出力で失敗させる
+    let (reader, writer) = std::io::pipe()?;
+    drop(reader);
+    let output = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .arg(&root)
+        .args(["--sort", "--delete", "--print"])
+        .stdout(writer)
+        .output()?;
+    assert!(output.status.success());
+    // 後回しにしたディレクトリは消そうとしない
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+    assert!(!root.join("a.txt").exists());
+    assert!(root.join("sub/b.txt").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+// パスと内容の組から、一時ディレクトリにファイルを作る。
+// 親ディレクトリも作り、末尾が / のパスは空のディレクトリにする
+fn make_tree(files: &[(&str, &str)]) -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    for (name, contents) in files {
+        let path = dir.path().join(name);
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, contents)?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+// .git はリポジトリに含められないため、一時ディレクトリに作る
+fn make_repo() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+    make_tree(&[
+        (".git/info/exclude", "*.log\n"),
+        (".gitignore", "build/\n*.tmp\n!keep.tmp\n"),
+        ("src/.gitignore", "*.rs\n!main.rs\n"),
+        ("src/.ignore", "gen\n"),
+        ("nested/.git/", ""),
+        ("nested/.gitignore", "n.tmp\n"),
+        ("a.log", ""),
+        ("b.txt", ""),
+        ("keep.tmp", ""),
+        ("x.tmp", ""),
+        ("build/o.o", ""),
+        ("src/main.rs", ""),
+        ("src/lib.rs", ""),
+        ("src/gen/g.c", ""),
+        ("sub/.env", ""),
+        ("sub/.hidden/h", ""),
+        ("nested/m.tmp", ""),
+        ("nested/n.tmp", ""),
+    ])
+}
+
+// --------------------------------------------------
+fn run_in(dir: &Path, args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let cmd = Command::cargo_bin(PRG)?
+        .current_dir(dir)
+        .args(args)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let mut lines: Vec<String> = stdout.lines().map(str::to_string).collect();
+    lines.sort();
+    Ok(lines)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn respect_ignore() -> TestResult {
+    let repo = make_repo()?;
+    assert_eq!(
+        run_in(repo.path(), &["--respect-ignore"])?,
+        [
+            ".",
+            "./b.txt",
+            "./keep.tmp",
+            "./nested",
+            "./nested/m.tmp",
+            "./src",
+            "./src/main.rs",
+            "./sub",
+        ]
+    );
+    assert_eq!(
+        run_in(repo.path(), &["src", "--respect-ignore"])?,
+        ["src", "src/main.rs"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn respect_ignore_hidden() -> TestResult {
+    let repo = make_repo()?;
+    assert_eq!(
+        run_in(repo.path(), &["--respect-ignore", "--hidden", "-t", "f"])?,
+        [
+            "./.gitignore",
+            "./b.txt",
+            "./keep.tmp",
+            "./nested/.gitignore",
+            "./nested/m.tmp",
+            "./src/.gitignore",
+            "./src/.ignore",
+            "./src/main.rs",
+            "./sub/.env",
+            "./sub/.hidden/h",
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn respect_ignore_delete() -> TestResult {
+    let repo = make_repo()?;
+    run_in(
+        repo.path(),
+        &["--respect-ignore", "-n", "[.]tmp$", "--delete"],
+    )?;
+    let root = repo.path();
+    assert!(!root.join("keep.tmp").exists());
+    assert!(!root.join("nested/m.tmp").exists());
+    assert!(root.join("x.tmp").exists());
+    assert!(root.join("nested/n.tmp").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_hidden_without_respect_ignore() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("--hidden")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--respect-ignore"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads() -> TestResult {
+    run(
+        &["tests/inputs", "--threads", "4"],
+        "tests/expected/path1.txt",
+    )?;
+    run(
+        &["tests/inputs", "--threads", "4", "-t", "f", "-n", "a"],
+        "tests/expected/type_f_name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_sort() -> TestResult {
+    let sequential = Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort"])
+        .output()?;
+    let parallel = Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "--threads", "4"])
+        .output()?;
+    assert!(parallel.status.success());
+    assert_eq!(
+        String::from_utf8(sequential.stdout)?,
+        String::from_utf8(parallel.stdout)?
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn threads_respect_ignore() -> TestResult {
+    let repo = make_repo()?;
+    assert_eq!(
+        run_in(repo.path(), &["--respect-ignore", "--threads", "4"])?,
+        run_in(repo.path(), &["--respect-ignore"])?,
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_delete() -> TestResult {
+    let dir = make_tree(&[("root/x/y/z/a.csv", ""), ("root/x/b.csv", "")])?;
+    let root = dir.path().join("root");
+
+    Command::cargo_bin(PRG)?
+        .arg(&root)
+        .args(["--threads", "4", "--delete"])
+        .assert()
+        .success();
+    assert!(!root.exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_threads() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--threads", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --threads \"0\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn iname() -> TestResult {
+    run(
+        &["tests/inputs", "--iname", "A[.]TXT"],
+        "tests/expected/iname_a_txt.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn glob_csv() -> TestResult {
+    run(
+        &["tests/inputs", "--glob", "*.csv"],
+        "tests/expected/glob_csv.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn path_glob() -> TestResult {
+    run(
+        &["tests/inputs", "--path", "*/b/*"],
+        "tests/expected/path_b.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn regex_path() -> TestResult {
+    run(
+        &["tests/inputs", "--regex", ".*/d/.*txt"],
+        "tests/expected/regex_d_txt.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_whole_path() -> TestResult {
+    // find と同様に、パスの一部だけに一致する場合は対象にしない
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--regex", "d/.*txt"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_glob() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--glob", "[a"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --glob \"[a\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn non_utf8_name() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = tempfile::tempdir()?;
+    let name = OsStr::from_bytes(b"bad\xff.txt");
+    fs::write(dir.path().join(name), "")?;
+
+    for args in [["--name", "txt$"], ["--glob", "bad*"], ["--iname", "BAD"]] {
+        let out = Command::cargo_bin(PRG)?
+            .arg(dir.path())
+            .args(args)
+            .output()?;
+        assert!(out.status.success());
+        assert!(out.stdout.ends_with(b"/bad\xff.txt\n"));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn make_perm_files() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let modes = [
+        ("rw-rw-rw-", 0o666),
+        ("rw-r--r--", 0o644),
+        ("rwsr-xr-x", 0o4755),
+    ];
+    let dir = make_tree(&modes.map(|(name, _)| (name, "")))?;
+    for (name, mode) in modes {
+        fs::set_permissions(dir.path().join(name), fs::Permissions::from_mode(mode))?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn perm() -> TestResult {
+    let dir = make_perm_files()?;
+    let path = dir.path().to_str().unwrap();
+    let cases: [(&str, &[&str]); 6] = [
+        ("644", &["rw-r--r--"]),
+        ("u=rw,go=r", &["rw-r--r--"]),
+        ("-o+w", &["rw-rw-rw-"]),
+        ("-444", &["rw-r--r--", "rw-rw-rw-", "rwsr-xr-x"]),
+        ("/4000", &["rwsr-xr-x"]),
+        ("/g=w,o=x", &["rw-rw-rw-", "rwsr-xr-x"]),
+    ];
+    for (mode, expected) in cases {
+        let lines = run_in(dir.path(), &[".", "-t", "f", "--perm", mode])?;
+        let expected: Vec<_> = expected.iter().map(|n| format!("./{}", n)).collect();
+        assert_eq!(lines, expected, "--perm {} in {}", mode, path);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn perm_followed_by_args() -> TestResult {
+    let dir = make_perm_files()?;
+    assert_eq!(
+        run_in(dir.path(), &["--perm", "-002", "-t", "f"])?,
+        ["./rw-rw-rw-"]
+    );
+    assert_eq!(
+        run_in(dir.path(), &["--perm", "644", "."])?,
+        ["./rw-r--r--"]
+    );
+    // 複数指定した場合は、いずれかに一致すればよい
+    assert_eq!(
+        run_in(dir.path(), &["--perm", "644", "--perm", "/4000", "."])?,
+        ["./rw-r--r--", "./rwsr-xr-x"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn user_and_group() -> TestResult {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = make_perm_files()?;
+    let metadata = fs::metadata(dir.path())?;
+    let uid = metadata.uid().to_string();
+    let gid = metadata.gid().to_string();
+
+    let lines = run_in(dir.path(), &["-t", "f", "--user", &uid, "--group", &gid])?;
+    assert_eq!(lines.len(), 3);
+
+    let other = (metadata.uid() + 1).to_string();
+    let lines = run_in(dir.path(), &["-t", "f", "--user", &other])?;
+    assert!(lines.is_empty());
+
+    let lines = run_in(
+        dir.path(),
+        &["--user", &other, "--user", &uid, ".", "-t", "f"],
+    )?;
+    assert_eq!(lines.len(), 3);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_user() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--user", "no-such-user-x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid --user \"no-such-user-x\"",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_perm() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--perm", "u+q"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --perm \"u+q\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn type_socket_fifo() -> TestResult {
+    let dir = make_tree(&[("file", "")])?;
+    let _listener = std::os::unix::net::UnixListener::bind(dir.path().join("sock"))?;
+    std::process::Command::new("mkfifo")
+        .arg(dir.path().join("pipe"))
+        .status()?;
+
+    for threads in ["1", "2"] {
+        assert_eq!(
+            run_in(dir.path(), &["-t", "s", "--threads", threads])?,
+            ["./sock"]
+        );
+        assert_eq!(
+            run_in(dir.path(), &["-t", "p", "--threads", threads])?,
+            ["./pipe"]
+        );
+        assert_eq!(
+            run_in(dir.path(), &["-t", "s", "p", "--threads", threads])?,
+            ["./pipe", "./sock"]
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_roots() -> TestResult {
+    let dir = Path::new("tests/links");
+    for threads in ["1", "2"] {
+        // find と同様に、-H や -L がなければ起点のリンクも辿らない
+        assert_eq!(run_in(dir, &["link", "--threads", threads])?, ["link"]);
+        assert_eq!(
+            run_in(dir, &["-H", "link", "--threads", threads])?,
+            ["link", "link/sub", "link/sub/f.txt", "link/sub/up"]
+        );
+        // 辿った起点はリンク先の種類になる
+        assert_eq!(
+            run_in(dir, &["-H", "link", "-t", "d", "--threads", threads])?,
+            ["link", "link/sub"]
+        );
+        assert_eq!(
+            run_in(dir, &["-H", "link", "-t", "l", "--threads", threads])?,
+            ["link/sub/up"]
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links_loop() -> TestResult {
+    let dir = Path::new("tests/links");
+    for threads in ["1", "2"] {
+        let cmd = Command::cargo_bin(PRG)?
+            .current_dir(dir)
+            .args(["-L", "d", "-t", "f", "--threads", threads])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "File system loop found: d/sub/up points to an ancestor d",
+            ));
+        let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+        assert_eq!(stdout, "d/sub/f.txt\n");
+
+        assert_eq!(
+            run_in(dir, &["-L", ".", "-t", "l", "--threads", threads])?,
+            ["./broken"]
+        );
+        assert_eq!(
+            run_in(dir, &["-L", "link", "-t", "d", "--threads", threads])?,
+            ["link", "link/sub"]
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn xdev() -> TestResult {
+    run(&["tests/inputs", "--xdev"], "tests/expected/path1.txt")?;
+    run(
+        &["tests/inputs", "--xdev", "--threads", "2"],
+        "tests/expected/path1.txt",
+    )
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn make_printf_files() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = make_tree(&[("d/five.txt", "12345")])?;
+    fs::set_permissions(
+        dir.path().join("d/five.txt"),
+        fs::Permissions::from_mode(0o4640),
+    )?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn printf() -> TestResult {
+    let dir = make_printf_files()?;
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["d", "-t", "f", "--printf", "%p|%f|%h|%s|%m|%M|%d\\n"]
+        )?,
+        ["d/five.txt|five.txt|d|5|4640|-rwSr-----|1"]
+    );
+    assert_eq!(
+        run_in(dir.path(), &["d", "--printf", "[%-6f]\\t[%3d]\\n"])?,
+        ["[d     ]\t[  0]", "[five.txt]\t[  1]"]
+    );
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["d", "-t", "f", "--printf", "%%\\101\\cignored"]
+        )?,
+        ["%A"]
+    );
+
+    let epoch = run_in(dir.path(), &["d", "-t", "f", "--printf", "%T@\\n"])?;
+    assert!(predicate::str::is_match(r"^\d+\.\d{9}$")?.eval(&epoch[0]));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn printf_with_print() -> TestResult {
+    let dir = make_printf_files()?;
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["d", "-t", "f", "--printf", "%s ", "--print"])
+        .assert()
+        .success()
+        .stdout("5 d/five.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn json() -> TestResult {
+    let dir = make_printf_files()?;
+    let lines = run_in(dir.path(), &["d", "--json"])?;
+    assert_eq!(lines.len(), 2);
+
+    let value: serde_json::Value = serde_json::from_str(&lines[1])?;
+    assert_eq!(value["path"], "d/five.txt");
+    assert_eq!(value["name"], "five.txt");
+    assert_eq!(value["depth"], 1);
+    assert_eq!(value["type"], "file");
+    assert_eq!(value["size"], 5);
+    assert_eq!(value["mode"], "4640");
+    assert_eq!(value["permissions"], "-rwSr-----");
+    assert!(value["uid"].is_u64());
+    assert!(value["user"].is_string());
+    assert!(value["mtime"].is_i64());
+
+    let value: serde_json::Value = serde_json::from_str(&lines[0])?;
+    assert_eq!(value["type"], "directory");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_printf() -> TestResult {
+    for bad in ["%z", "\\q", "%"] {
+        Command::cargo_bin(PRG)?
+            .args(["--printf", bad])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(format!(
+                "Invalid --printf \"{}\"",
+                bad
+            )));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+fn make_duplicates() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+    make_tree(&[
+        ("a.txt", "same"),
+        ("sub/b.txt", "same"),
+        ("c.txt", "diff"),
+        ("empty1", ""),
+        ("empty2", ""),
+    ])
+}
+
+// --------------------------------------------------
+#[test]
+fn duplicates() -> TestResult {
+    // big1 と big3 は、先頭部分は同じで途中から内容が異なる
+    let expected = fs::read_to_string("tests/expected/duplicates.txt")?;
+    for threads in ["1", "2"] {
+        Command::cargo_bin(PRG)?
+            .current_dir("tests/duplicates")
+            .args(["--duplicates", "--threads", threads])
+            .assert()
+            .success()
+            .stdout(expected.clone());
+    }
+    Command::cargo_bin(PRG)?
+        .current_dir("tests/duplicates")
+        .args(["--duplicates", "--name", "txt"])
+        .assert()
+        .success()
+        .stdout("./a.txt\n./sub/b.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn duplicates_dry_run() -> TestResult {
+    let dir = Path::new("tests/duplicates");
+    Command::cargo_bin(PRG)?
+        .current_dir(dir)
+        .args(["--duplicates", "--dedupe", "delete", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(fs::read_to_string("tests/expected/duplicates_dry_run.txt")?);
+    assert!(dir.join("sub/b.txt").exists());
+    assert!(dir.join("sub/big2").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn duplicates_delete() -> TestResult {
+    let dir = make_duplicates()?;
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["--duplicates", "--dedupe", "delete"])
+        .assert()
+        .success();
+    assert_eq!(
+        run_in(dir.path(), &["-t", "f"])?,
+        ["./a.txt", "./c.txt", "./empty1", "./empty2"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn duplicates_outside_root() -> TestResult {
+    let dir = make_tree(&[("root/a.txt", "same"), ("outside/b.txt", "same")])?;
+    let root = dir.path().join("root");
+    std::os::unix::fs::symlink("../outside", root.join("link"))?;
+
+    // -L で辿った先のファイルは、開始パスの外にあるため変更しない
+    for (dedupe, verb) in [("delete", "delete"), ("hardlink", "link")] {
+        Command::cargo_bin(PRG)?
+            .current_dir(&root)
+            .args(["-L", "--duplicates", "--dedupe", dedupe])
+            .assert()
+            .failure()
+            .stdout("./a.txt\n./link/b.txt\n")
+            .stderr(predicate::str::contains(format!(
+                "./link/b.txt: refusing to {} outside of",
+                verb
+            )));
+    }
+    assert!(dir.path().join("outside/b.txt").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn duplicates_hardlink() -> TestResult {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = make_duplicates()?;
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["--duplicates", "--dedupe", "hardlink"])
+        .assert()
+        .success();
+    let ino = |path: &str| fs::metadata(dir.path().join(path)).map(|m| m.ino());
+    assert_eq!(ino("a.txt")?, ino("sub/b.txt")?);
+    assert_ne!(ino("a.txt")?, ino("c.txt")?);
+    assert_eq!(fs::read_to_string(dir.path().join("sub/b.txt"))?, "same");
+
+    // ハードリンク済みのファイルは重複として扱わない
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .arg("--duplicates")
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_duplicates_with_action() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--duplicates", "--delete"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Command::cargo_bin(PRG)?
+        .args(["--dedupe", "delete"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--duplicates"));
+    Ok(())
+}
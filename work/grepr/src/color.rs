@@ -0,0 +1,142 @@
+use ansi_term::{Colour, Style};
+
+// GREP_COLORS の ms、fn、ln、bn、se に対応する。既定値は grep と同じ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colors {
+    pub matched: Style,
+    pub filename: Style,
+    pub line_number: Style,
+    pub byte_offset: Style,
+    pub separator: Style,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            matched: Colour::Red.bold(),
+            filename: Colour::Purple.normal(),
+            line_number: Colour::Green.normal(),
+            byte_offset: Colour::Green.normal(),
+            separator: Colour::Cyan.normal(),
+        }
+    }
+}
+
+impl Colors {
+    // "ms=01;31:fn=35" の形式。grep と同様に、解釈できない項目は無視する
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+        for item in spec.split(':') {
+            let Some((name, value)) = item.split_once('=') else {
+                continue;
+            };
+            let Some(style) = parse_sgr(value) else {
+                continue;
+            };
+            match name {
+                "mt" | "ms" => colors.matched = style,
+                "fn" => colors.filename = style,
+                "ln" => colors.line_number = style,
+                "bn" => colors.byte_offset = style,
+                "se" => colors.separator = style,
+                _ => {}
+            }
+        }
+        colors
+    }
+}
+
+// SGR のパラメータを ";" で区切ったもの。空の場合は色を付けない
+fn parse_sgr(value: &str) -> Option<Style> {
+    let mut style = Style::new();
+    if value.is_empty() {
+        return Some(style);
+    }
+    let mut params = value
+        .split(';')
+        .map(|param| param.parse::<u8>().ok())
+        .collect::<Option<Vec<_>>>()?
+        .into_iter();
+    while let Some(param) = params.next() {
+        match param {
+            0 => style = Style::new(),
+            1 => style = style.bold(),
+            2 => style = style.dimmed(),
+            3 => style = style.italic(),
+            4 => style = style.underline(),
+            5 => style = style.blink(),
+            7 => style = style.reverse(),
+            8 => style = style.hidden(),
+            9 => style = style.strikethrough(),
+            30..=37 => style = style.fg(colour(param - 30)),
+            38 => style = style.fg(extended(&mut params)?),
+            39 => style.foreground = None,
+            40..=47 => style = style.on(colour(param - 40)),
+            48 => style = style.on(extended(&mut params)?),
+            49 => style.background = None,
+            90..=97 => style = style.fg(Colour::Fixed(param - 90 + 8)),
+            100..=107 => style = style.on(Colour::Fixed(param - 100 + 8)),
+            _ => return None,
+        }
+    }
+    Some(style)
+}
+
+fn colour(index: u8) -> Colour {
+    match index {
+        0 => Colour::Black,
+        1 => Colour::Red,
+        2 => Colour::Green,
+        3 => Colour::Yellow,
+        4 => Colour::Blue,
+        5 => Colour::Purple,
+        6 => Colour::Cyan,
+        _ => Colour::White,
+    }
+}
+
+// 38 や 48 に続く "5;N" または "2;R;G;B"
+fn extended(params: &mut impl Iterator<Item = u8>) -> Option<Colour> {
+    match params.next()? {
+        5 => Some(Colour::Fixed(params.next()?)),
+        2 => Some(Colour::RGB(params.next()?, params.next()?, params.next()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Colors, parse_sgr};
+    use ansi_term::{Colour, Style};
+
+    #[test]
+    fn test_parse_sgr() {
+        assert_eq!(parse_sgr(""), Some(Style::new()));
+        assert_eq!(parse_sgr("01;31"), Some(Colour::Red.bold()));
+        assert_eq!(
+            parse_sgr("4;92;44"),
+            Some(Colour::Fixed(10).on(Colour::Blue).underline())
+        );
+        assert_eq!(parse_sgr("38;5;208"), Some(Colour::Fixed(208).normal()));
+        assert_eq!(
+            parse_sgr("48;2;1;2;3"),
+            Some(Style::new().on(Colour::RGB(1, 2, 3)))
+        );
+        assert_eq!(parse_sgr("1;0;32"), Some(Colour::Green.normal()));
+        for bad in ["x", "1;", "38;5", "38;7;1", "256", "6"] {
+            assert_eq!(parse_sgr(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let colors = Colors::parse("ms=04;32:fn=:ln=x:ne:sl=1:se=33");
+        assert_eq!(colors.matched, Colour::Green.underline());
+        assert_eq!(colors.filename, Style::new());
+        assert_eq!(colors.line_number, Colors::default().line_number);
+        assert_eq!(colors.separator, Colour::Yellow.normal());
+
+        assert_eq!(Colors::parse("mt=1").matched, Style::new().bold());
+        assert_eq!(Colors::parse(""), Colors::default());
+    }
+}
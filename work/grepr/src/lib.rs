@@ -1,240 +1,995 @@
-use clap::{App, Arg};
-use regex::{Regex, RegexBuilder};
-use walkdir::WalkDir;
-use std::{error::Error, fmt::format, fs::File, io::{BufRead, BufReader}, path::PathBuf};
-
-type MyResult<T> = Result<T, Box<dyn Error>>;
-
-#[derive(Debug)]
-pub struct Config {
-    pattern: Regex,
-    files: Vec<String>,
-    recursive: bool,
-    count: bool,
-    invert_match: bool,
-}
-
-pub fn run(config: Config) -> MyResult<()> {
-    let entries = find_files(&config.files, config.recursive);
-    for entry in entries {
-        match entry {
-            Err(e)=> eprintln!("{}", e),
-            Ok(filename) => match open(&filename) {
-                Err(e) => eprintln!("{}: {}", filename, e),
-                Ok(file) => {
-                    let matches = find_lines(file, &config.pattern, config.invert_match);
-                    match matches {
-                        Err(e) => eprintln!("{}: {}", filename, e),
-                        Ok(lines) => {
-                            if config.count {
-                                if &config.files.len() > &1 || config.recursive {
-                                    print!("{}:", filename);
-                                }
-                                println!("{}", lines.len());
-                            } else {
-                                for line in lines {
-                                    if &config.files.len() > &1 || config.recursive {
-                                       print!("{}:", filename);
-                                    }
-                                    print!("{}", line);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-    Ok(())
-}
-
-pub fn get_args() -> MyResult<Config> {
-        let matches = App::new("grepr")
-        .version("0.1.0")
-        .author("John Doe")
-        .about("Rust grep")
-        .arg(
-            Arg::with_name("pattern")
-            .value_name("PATTERN")
-            .help("Search pattern")
-            .required(true)
-        )
-        .arg(
-            Arg::with_name("file")
-            .value_name("FILE")
-            .help("Input file(s)")
-            .default_value("-")
-            .multiple(true)
-        )
-        .arg(
-            Arg::with_name("count")
-            .short("c")
-            .long("count")
-            .help("Count occurrences")
-            .takes_value(false)
-        )
-        .arg(
-            Arg::with_name("insensitive")
-            .short("i")
-            .long("insensitive")
-            .help("Case-insensitive")
-            .takes_value(false)
-        )
-        .arg(
-            Arg::with_name("invert-match")
-            .short("v")
-            .long("invert-match")
-            .help("Invert match")
-            .takes_value(false)
-        )
-        .arg(
-            Arg::with_name("recursive")
-            .short("r")
-            .long("recursive")
-            .help("Recursive search")
-            .takes_value(false)
-        )
-
-        .get_matches();
-
-    let pattern = RegexBuilder::new(matches.value_of("pattern").unwrap())
-        .case_insensitive(matches.is_present("insensitive"))
-        .build().map_err(|_| format!("Invalid pattern \"{}\"\n", matches.value_of("pattern").unwrap()))?;
-
-    Ok(Config {
-        pattern: pattern,
-        files: matches.values_of("file").unwrap().map(|s| s.to_string()).collect(),
-        recursive: matches.is_present("recursive"),
-        count: matches.is_present("count"),
-        invert_match: matches.is_present("invert-match"),
-    })
-}
-
-fn find_files(path: &[String], recursive: bool) -> Vec<MyResult<String>> {
-    path.iter().map(|p| {
-        if p == "-" {
-            return vec![Ok("-".to_string())];
-        }
-        let path = PathBuf::from(p);
-        if !path.exists() {
-            return vec![Err(format!("{}: does not exist", p).into())];
-        }
-        if path.is_file() {
-            return vec![Ok(p.clone())];
-        }
-        if path.is_dir() {
-            if !recursive {
-                return vec![Err(format!("{} is a directory", p).into())];
-            }
-            let dirs:Vec<_> = WalkDir::new(path).min_depth(1).into_iter().filter_map(Result::ok).map(|p| {p.path().to_string_lossy().to_string()}).collect();
-            return find_files(&dirs, true);
-        }
-        vec![Ok(p.clone())]
-    }).flatten().collect()
-}
-
-fn find_lines<T: BufRead> (mut file: T, pattern: &Regex, invert_match: bool) -> MyResult<Vec<String>> {
-    let mut matches = vec![];
-    let mut line = String::new();
-
-    while file.read_line(&mut line)? > 0 {
-        if pattern.is_match(&line) != invert_match {
-            matches.push(std::mem::take(&mut line));
-        }
-        line.clear();
-    }
-    Ok(matches)
-}
-
-fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(std::io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?)))
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::{find_files, find_lines};
-    use rand::{distributions::Alphanumeric, Rng};
-    use regex::{Regex, RegexBuilder};
-    use std::io::Cursor;
-
-    #[test]
-    fn test_find_lines() {
-        let text = b"Lorem\nIpsum\r\nDOLOR";
-
-        // The pattern _or_ should match the one line, "Lorem"
-        let re1 = Regex::new("or").unwrap();
-        let matches = find_lines(Cursor::new(&text), &re1, false);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 1);
-
-        // When inverted, the function should match the other two lines
-        let matches = find_lines(Cursor::new(&text), &re1, true);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 2);
-
-        // This regex will be case-insensitive
-        let re2 = RegexBuilder::new("or")
-            .case_insensitive(true)
-            .build()
-            .unwrap();
-
-        // The two lines "Lorem" and "DOLOR" should match
-        let matches = find_lines(Cursor::new(&text), &re2, false);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 2);
-
-        // When inverted, the one remaining line should match
-        let matches = find_lines(Cursor::new(&text), &re2, true);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 1);
-    }
-
-    #[test]
-    fn test_find_files() {
-        // Verify that the function finds a file known to exist
-        let files =
-            find_files(&["./tests/inputs/fox.txt".to_string()], false);
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
-
-        // The function should reject a directory without the recursive option
-        let files = find_files(&["./tests/inputs".to_string()], false);
-        assert_eq!(files.len(), 1);
-        if let Err(e) = &files[0] {
-            assert_eq!(e.to_string(), "./tests/inputs is a directory");
-        }
-
-        // Verify the function recurses to find four files in the directory
-        let res = find_files(&["./tests/inputs".to_string()], true);
-        let mut files: Vec<String> = res
-            .iter()
-            .map(|r| r.as_ref().unwrap().replace("\\", "/"))
-            .collect();
-        files.sort();
-        assert_eq!(files.len(), 4);
-        assert_eq!(
-            files,
-            vec![
-                "./tests/inputs/bustle.txt",
-                "./tests/inputs/empty.txt",
-                "./tests/inputs/fox.txt",
-                "./tests/inputs/nobody.txt",
-            ]
-        );
-
-        // Generate a random string to represent a nonexistent file
-        let bad: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(7)
-            .map(char::from)
-            .collect();
-
-        // Verify that the function returns the bad file as an error
-        let files = find_files(&[bad], false);
-        assert_eq!(files.len(), 1);
-        assert!(files[0].is_err());
-    }
-}
+mod color;
+mod matcher;
+
+use ansi_term::Style;
+use clap::{App, Arg, ArgMatches};
+use color::Colors;
+use matcher::{Matcher, Options};
+use std::{
+    collections::VecDeque,
+    env,
+    error::Error,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    path::PathBuf,
+};
+use walkdir::WalkDir;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Debug)]
+pub struct Config {
+    matcher: Matcher,
+    files: Vec<String>,
+    recursive: bool,
+    count: bool,
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    line_number: bool,
+    byte_offset: bool,
+    with_filename: bool,
+    label: String,
+    files_with_matches: bool,
+    files_without_match: bool,
+    quiet: bool,
+    max_count: Option<usize>,
+    only_matching: bool,
+    // -o で出力するキャプチャグループの番号。0 は一致した部分全体
+    group: usize,
+    replace: Option<String>,
+    // 色を付けない場合は None
+    colors: Option<Colors>,
+    binary_files: BinaryFiles,
+}
+
+// NUL バイトを含むファイルの扱い。grep の --binary-files と同じ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFiles {
+    Binary,
+    Text,
+    WithoutMatch,
+}
+
+impl Config {
+    fn style(&self, style: impl Fn(&Colors) -> Style) -> Style {
+        self.colors.as_ref().map_or_else(Style::new, style)
+    }
+}
+
+// number は 1 から数えた行番号、offset はファイルの先頭から行の先頭までのバイト数。
+// text は UTF-8 とは限らない
+#[derive(Debug, PartialEq, Eq)]
+struct Line<'a> {
+    number: usize,
+    offset: usize,
+    text: &'a [u8],
+}
+
+// Separator は離れた行のまとまりの間に入れる。
+// Binary はバイナリファイルで一致する行があったことを表す
+#[derive(Debug, PartialEq, Eq)]
+enum Print<'a> {
+    Match(Line<'a>),
+    Context(Line<'a>),
+    Separator,
+    Binary,
+}
+
+// 一致する行があったかどうかと、読めないファイルがあったかどうかを返す。
+// -L の場合は、出力したファイルがあったかどうか
+pub fn run(config: Config) -> MyResult<(bool, bool)> {
+    let mut found = false;
+    let mut failed = false;
+    // 出力先のパイプが閉じられた場合は、それまでの結果で終了する
+    match grep(&config, &mut found, &mut failed) {
+        Err(e) if is_broken_pipe(e.as_ref()) => Ok((found, failed)),
+        result => result.map(|_| (found, failed)),
+    }
+}
+
+fn grep(config: &Config, found: &mut bool, failed: &mut bool) -> MyResult<()> {
+    let mut stdout = io::stdout().lock();
+    // 区切りは、前に何か出力した場合だけ出力する。ファイルをまたぐ場合も同様
+    let mut printed = false;
+    let entries = find_files(&config.files, config.recursive);
+    for entry in entries {
+        match entry {
+            Err(e) => {
+                eprintln!("{}", e);
+                *failed = true;
+            }
+            Ok(filename) => {
+                let name = if filename == "-" {
+                    &config.label
+                } else {
+                    &filename
+                };
+                match open(&filename) {
+                    Err(e) => {
+                        eprintln!("{}: {}", name, e);
+                        *failed = true;
+                    }
+                    Ok(file) => {
+                        let matches = find_lines(file, config, |print| {
+                            if config.count
+                                || config.quiet
+                                || config.files_with_matches
+                                || config.files_without_match
+                            {
+                                return Ok(());
+                            }
+                            match print {
+                                Print::Separator if !printed => {}
+                                Print::Separator => writeln!(
+                                    stdout,
+                                    "{}",
+                                    config.style(|c| c.separator).paint("--")
+                                )?,
+                                Print::Match(line) => {
+                                    write_match(&mut stdout, config, name, &line)?
+                                }
+                                // grep と同様に、-o では文脈の行を出力しない
+                                Print::Context(_) if config.only_matching => {}
+                                Print::Context(line) => {
+                                    write_line(&mut stdout, config, name, '-', &line)?
+                                }
+                                Print::Binary => writeln!(stdout, "Binary file {} matches", name)?,
+                            }
+                            printed = true;
+                            Ok(())
+                        });
+                        match matches {
+                            // 行を出力しようとした時点で、このファイルには一致がある
+                            Err(e) if is_broken_pipe(e.as_ref()) => {
+                                *found = true;
+                                return Err(e);
+                            }
+                            Err(e) => {
+                                eprintln!("{}: {}", name, e);
+                                *failed = true;
+                            }
+                            // 一つでも一致すれば結果は決まるため、残りのファイルは読まない。
+                            // grep と同様に、それまでのエラーも無視する
+                            Ok(count) if config.quiet && count > 0 => {
+                                *found = true;
+                                *failed = false;
+                                return Ok(());
+                            }
+                            Ok(_) if config.quiet => {}
+                            Ok(count) if config.files_without_match => {
+                                if count == 0 {
+                                    *found = true;
+                                    writeln!(
+                                        stdout,
+                                        "{}",
+                                        config.style(|c| c.filename).paint(name)
+                                    )?;
+                                }
+                            }
+                            Ok(count) if config.files_with_matches => {
+                                if count > 0 {
+                                    *found = true;
+                                    writeln!(
+                                        stdout,
+                                        "{}",
+                                        config.style(|c| c.filename).paint(name)
+                                    )?;
+                                }
+                            }
+                            Ok(count) if config.count => {
+                                *found |= count > 0;
+                                if config.with_filename {
+                                    write!(
+                                        stdout,
+                                        "{}{}",
+                                        config.style(|c| c.filename).paint(name),
+                                        config.style(|c| c.separator).paint(":")
+                                    )?;
+                                }
+                                writeln!(stdout, "{}", count)?;
+                            }
+                            Ok(count) => *found |= count > 0,
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// 一致した部分に色を付ける。-o では一致した部分ごとに一行ずつ出力し、
+// バイト位置は一致した部分の先頭とする
+fn write_match(
+    out: &mut impl Write,
+    config: &Config,
+    filename: &str,
+    line: &Line,
+) -> io::Result<()> {
+    // 行末の改行は、-x や $ で一致するように除いて探す
+    let content = line.text.strip_suffix(b"\n").unwrap_or(line.text);
+    let replace = config.replace.as_deref();
+    let matched = config.style(|c| c.matched);
+    let paint = |text: &mut Vec<u8>, value: &[u8]| {
+        if value.is_empty() {
+            return Ok(());
+        }
+        matched.paint(value).write_to(text)
+    };
+    if !config.only_matching {
+        if replace.is_none() && config.colors.is_none() {
+            return write_line(out, config, filename, ':', line);
+        }
+        let mut text = vec![];
+        let mut end = 0;
+        config
+            .matcher
+            .each_match(content, 0, replace, |range, value| {
+                text.extend_from_slice(&content[end..range.start]);
+                paint(&mut text, value)?;
+                end = range.end;
+                Ok(())
+            })?;
+        text.extend_from_slice(&line.text[end..]);
+        return write_line(
+            out,
+            config,
+            filename,
+            ':',
+            &Line {
+                text: &text,
+                ..*line
+            },
+        );
+    }
+    let mut text = vec![];
+    config
+        .matcher
+        .each_match(content, config.group, replace, |range, value| {
+            // 空の一致は出力しない
+            if range.is_empty() {
+                return Ok(());
+            }
+            text.clear();
+            paint(&mut text, value)?;
+            text.push(b'\n');
+            let matched = Line {
+                offset: line.offset + range.start,
+                text: &text,
+                ..*line
+            };
+            write_line(out, config, filename, ':', &matched)
+        })
+}
+
+// grep と同様に、ファイル名、行番号、バイト位置の順に sep で区切って前に付ける
+fn write_line(
+    out: &mut impl Write,
+    config: &Config,
+    filename: &str,
+    sep: char,
+    line: &Line,
+) -> io::Result<()> {
+    let sep = config.style(|c| c.separator).paint(sep.to_string());
+    if config.with_filename {
+        write!(
+            out,
+            "{}{}",
+            config.style(|c| c.filename).paint(filename),
+            sep
+        )?;
+    }
+    if config.line_number {
+        let number = line.number.to_string();
+        write!(
+            out,
+            "{}{}",
+            config.style(|c| c.line_number).paint(number),
+            sep
+        )?;
+    }
+    if config.byte_offset {
+        let offset = line.offset.to_string();
+        write!(
+            out,
+            "{}{}",
+            config.style(|c| c.byte_offset).paint(offset),
+            sep
+        )?;
+    }
+    out.write_all(line.text)
+}
+
+pub fn get_args() -> MyResult<Config> {
+    let matches = App::new("grepr")
+        .version("0.1.0")
+        .author("John Doe")
+        .about("Rust grep")
+        .arg(
+            Arg::with_name("pattern")
+                .value_name("PATTERN")
+                .help("Search pattern, or the first file with -e or -f")
+                .required_unless_one(&["regexp", "pattern-file"]),
+        )
+        .arg(
+            Arg::with_name("file")
+                .value_name("FILE")
+                .help("Input file(s)")
+                .default_value("-")
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("regexp")
+                .value_name("PATTERN")
+                .short("e")
+                .long("regexp")
+                .help("Search pattern, may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("pattern-file")
+                .value_name("FILE")
+                .short("f")
+                .long("file")
+                .help("Read patterns from FILE, one per line")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("fixed-strings")
+                .short("F")
+                .long("fixed-strings")
+                .help("Treat patterns as literal strings")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("word-regexp")
+                .short("w")
+                .long("word-regexp")
+                .help("Match only whole words")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("line-regexp")
+                .short("x")
+                .long("line-regexp")
+                .help("Match only whole lines")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("count")
+                .short("c")
+                .long("count")
+                .help("Count occurrences")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("insensitive")
+                .short("i")
+                .long("insensitive")
+                .help("Case-insensitive")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("invert-match")
+                .short("v")
+                .long("invert-match")
+                .help("Invert match")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .help("Recursive search")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("after-context")
+                .value_name("NUM")
+                .short("A")
+                .long("after-context")
+                .help("Print NUM lines of trailing context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("before-context")
+                .value_name("NUM")
+                .short("B")
+                .long("before-context")
+                .help("Print NUM lines of leading context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .value_name("NUM")
+                .short("C")
+                .long("context")
+                .help("Print NUM lines of output context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("line-number")
+                .short("n")
+                .long("line-number")
+                .help("Print line numbers")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("byte-offset")
+                .short("b")
+                .long("byte-offset")
+                .help("Print the byte offset of each line")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("with-filename")
+                .short("H")
+                .long("with-filename")
+                .help("Print the file name for each match")
+                .takes_value(false)
+                .overrides_with("no-filename"),
+        )
+        .arg(
+            Arg::with_name("no-filename")
+                .short("h")
+                .long("no-filename")
+                .help("Suppress the file name prefix")
+                .takes_value(false)
+                .overrides_with("with-filename"),
+        )
+        .arg(
+            Arg::with_name("files-with-matches")
+                .short("l")
+                .long("files-with-matches")
+                .help("Print only the names of files with matches")
+                .takes_value(false)
+                .conflicts_with_all(&["files-without-match", "count"]),
+        )
+        .arg(
+            Arg::with_name("files-without-match")
+                .short("L")
+                .long("files-without-match")
+                .help("Print only the names of files without matches")
+                .takes_value(false)
+                .conflicts_with("count"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Print nothing and exit with zero status on the first match")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max-count")
+                .value_name("NUM")
+                .short("m")
+                .long("max-count")
+                .help("Stop reading a file after NUM matching lines")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("only-matching")
+                .short("o")
+                .long("only-matching")
+                .help("Print only the matched parts, one per line")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("group")
+                .value_name("GROUP")
+                .long("group")
+                .help("Print the capture group GROUP (number or name) with -o")
+                .takes_value(true)
+                .requires("only-matching"),
+        )
+        .arg(
+            Arg::with_name("replace")
+                .value_name("TEMPLATE")
+                .long("replace")
+                .help("Replace every match with TEMPLATE, expanding $1 and ${name} (as is with -F)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("color")
+                .value_name("WHEN")
+                .long("color")
+                .help("Highlight matches: auto (if stdout is a terminal), always or never")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(&["auto", "always", "never"]),
+        )
+        .arg(
+            Arg::with_name("text")
+                .short("a")
+                .long("text")
+                .help("Process a binary file as if it were text")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("binary-files")
+                .value_name("TYPE")
+                .long("binary-files")
+                .help("How to handle binary files")
+                .takes_value(true)
+                .possible_values(&["binary", "text", "without-match"])
+                .default_value("binary"),
+        )
+        .arg(
+            Arg::with_name("label")
+                .value_name("LABEL")
+                .long("label")
+                .help("File name to print for standard input")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let mut files: Vec<String> = matches
+        .values_of("file")
+        .unwrap()
+        .map(|s| s.to_string())
+        .collect();
+    // -e や -f がある場合は、最初の引数もファイルとみなす
+    let patterns = if matches.is_present("regexp") || matches.is_present("pattern-file") {
+        if let Some(file) = matches.value_of("pattern") {
+            if matches.occurrences_of("file") == 0 {
+                files.clear();
+            }
+            files.insert(0, file.to_string());
+        }
+        let mut patterns: Vec<String> = matches
+            .values_of("regexp")
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+            .collect();
+        for filename in matches.values_of("pattern-file").into_iter().flatten() {
+            let text = fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            patterns.extend(text.lines().map(str::to_string));
+        }
+        patterns
+    } else {
+        vec![matches.value_of("pattern").unwrap().to_string()]
+    };
+    let matcher = Matcher::new(
+        &patterns,
+        &Options {
+            fixed_strings: matches.is_present("fixed-strings"),
+            insensitive: matches.is_present("insensitive"),
+            word: matches.is_present("word-regexp"),
+            line: matches.is_present("line-regexp"),
+        },
+    )?;
+    // grep と同様に、-A や -B は -C より優先する
+    let context = parse_context(&matches, "context")?.unwrap_or(0);
+    let group = match matches.value_of("group") {
+        None => 0,
+        Some(group) => group
+            .parse()
+            .ok()
+            .filter(|&i| i < matcher.captures_len())
+            .or_else(|| matcher.group_index(group))
+            .ok_or_else(|| format!("Invalid group \"{}\"", group))?,
+    };
+    let recursive = matches.is_present("recursive");
+    // --color だけの場合は auto とする
+    let color = match matches.value_of("color") {
+        _ if !matches.is_present("color") => false,
+        Some("always") => true,
+        Some("never") => false,
+        _ => io::stdout().is_terminal() && env::var("TERM").is_ok_and(|term| term != "dumb"),
+    };
+    let with_filename = if matches.is_present("with-filename") {
+        true
+    } else if matches.is_present("no-filename") {
+        false
+    } else {
+        files.len() > 1 || recursive
+    };
+
+    Ok(Config {
+        matcher,
+        files,
+        recursive,
+        count: matches.is_present("count"),
+        invert_match: matches.is_present("invert-match"),
+        before_context: parse_context(&matches, "before-context")?.unwrap_or(context),
+        after_context: parse_context(&matches, "after-context")?.unwrap_or(context),
+        line_number: matches.is_present("line-number"),
+        byte_offset: matches.is_present("byte-offset"),
+        with_filename,
+        label: matches.value_of("label").unwrap_or("-").to_string(),
+        files_with_matches: matches.is_present("files-with-matches"),
+        files_without_match: matches.is_present("files-without-match"),
+        quiet: matches.is_present("quiet"),
+        max_count: matches
+            .value_of("max-count")
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid max count \"{}\"", value))
+            })
+            .transpose()?,
+        only_matching: matches.is_present("only-matching"),
+        group,
+        replace: matches.value_of("replace").map(str::to_string),
+        colors: color.then(|| Colors::parse(&env::var("GREP_COLORS").unwrap_or_default())),
+        binary_files: match matches.value_of("binary-files") {
+            _ if matches.is_present("text") => BinaryFiles::Text,
+            Some("text") => BinaryFiles::Text,
+            Some("without-match") => BinaryFiles::WithoutMatch,
+            _ => BinaryFiles::Binary,
+        },
+    })
+}
+
+fn parse_context(matches: &ArgMatches, name: &str) -> MyResult<Option<usize>> {
+    matches
+        .value_of(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid context length \"{}\"", value).into())
+        })
+        .transpose()
+}
+
+fn find_files(path: &[String], recursive: bool) -> Vec<MyResult<String>> {
+    path.iter()
+        .flat_map(|p| {
+            if p == "-" {
+                return vec![Ok("-".to_string())];
+            }
+            let path = PathBuf::from(p);
+            if let Err(e) = fs::metadata(&path) {
+                return vec![Err(format!("{}: {}", p, e).into())];
+            }
+            if path.is_file() {
+                return vec![Ok(p.clone())];
+            }
+            if path.is_dir() {
+                if !recursive {
+                    return vec![Err(format!("{} is a directory", p).into())];
+                }
+                let dirs: Vec<_> = WalkDir::new(path)
+                    .min_depth(1)
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .map(|p| p.path().to_string_lossy().to_string())
+                    .collect();
+                return find_files(&dirs, true);
+            }
+            vec![Ok(p.clone())]
+        })
+        .collect()
+}
+
+// 一致した行と前後の行を順に print に渡し、一致した行の数を返す。
+// 前の行は、直近の before_context 行だけを使い回すバッファに残しておく。
+// 一致した行の数が上限に達したら、後ろの文脈を出力し終えた時点で読むのをやめる。
+// grep と同様に、NUL バイトがあればバイナリファイルとみなし、以降は行を出力しない
+fn find_lines<T: BufRead>(
+    mut file: T,
+    config: &Config,
+    mut print: impl FnMut(Print) -> MyResult<()>,
+) -> MyResult<usize> {
+    let mut count = 0;
+    let mut before: VecDeque<(usize, Vec<u8>)> = VecDeque::with_capacity(config.before_context);
+    let mut after = 0;
+    // 最後に出力した行の番号
+    let mut last_printed = None;
+    let mut text = vec![];
+    let mut number = 0;
+    let mut offset = 0;
+    // ファイル名や終了ステータスだけが必要な場合は、最初の一致で答えが決まる
+    let list = config.quiet || config.files_with_matches || config.files_without_match;
+    let (max_count, after_context) = if list {
+        (Some(1), 0)
+    } else {
+        (config.max_count, config.after_context)
+    };
+    // 行を出力しない場合は、バイナリファイルでも一致した行を数える
+    let print_lines = !list && !config.count;
+    // 最初に読み込んだ部分で判定し、それ以降は行ごとに調べる
+    let mut binary = config.binary_files != BinaryFiles::Text && file.fill_buf()?.contains(&0);
+
+    loop {
+        let done = max_count.is_some_and(|max| count >= max);
+        if done && after == 0 {
+            break;
+        }
+        text.clear();
+        let len = file.read_until(b'\n', &mut text)?;
+        if len == 0 {
+            break;
+        }
+        binary |= config.binary_files != BinaryFiles::Text && text.contains(&0);
+        if binary {
+            match config.binary_files {
+                // 残りの部分には一致しないとみなす
+                BinaryFiles::WithoutMatch => break,
+                BinaryFiles::Binary if print_lines => {
+                    after = 0;
+                    before.clear();
+                }
+                _ => {}
+            }
+        }
+        number += 1;
+        let line = Line {
+            number,
+            offset,
+            text: &text,
+        };
+        offset += len;
+        let content = text.strip_suffix(b"\n").unwrap_or(&text);
+        if !done && config.matcher.is_match(content) != config.invert_match {
+            count += 1;
+            if binary && print_lines {
+                print(Print::Binary)?;
+                break;
+            }
+            let first = number - before.len();
+            let context = config.before_context > 0 || config.after_context > 0;
+            if context && last_printed.is_none_or(|last| last + 1 < first) {
+                print(Print::Separator)?;
+            }
+            for (i, (offset, text)) in before.iter().enumerate() {
+                print(Print::Context(Line {
+                    number: first + i,
+                    offset: *offset,
+                    text,
+                }))?;
+            }
+            print(Print::Match(line))?;
+            before.clear();
+            after = after_context;
+            last_printed = Some(number);
+        } else if after > 0 {
+            print(Print::Context(line))?;
+            after -= 1;
+            last_printed = Some(number);
+        } else if config.before_context > 0 && !(binary && print_lines) {
+            let mut buffer = if before.len() == config.before_context {
+                before.pop_front().unwrap().1
+            } else {
+                vec![]
+            };
+            buffer.clone_from(&text);
+            before.push_back((line.offset, buffer));
+        }
+    }
+    Ok(count)
+}
+
+fn is_broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
+    match filename {
+        "-" => Ok(Box::new(BufReader::new(std::io::stdin()))),
+        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryFiles, Config, Matcher, Print, find_files, find_lines};
+    use rand::{Rng, distributions::Alphanumeric};
+    use regex::bytes::{Regex, RegexBuilder};
+    use std::io::Cursor;
+
+    fn lossy(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).trim_end().to_string()
+    }
+
+    fn config(pattern: &Regex, invert_match: bool, before: usize, after: usize) -> Config {
+        Config {
+            matcher: Matcher::Regex {
+                regex: pattern.clone(),
+                expand: true,
+                word: None,
+            },
+            files: vec![],
+            recursive: false,
+            count: false,
+            invert_match,
+            before_context: before,
+            after_context: after,
+            line_number: false,
+            byte_offset: false,
+            with_filename: false,
+            label: "-".to_string(),
+            files_with_matches: false,
+            files_without_match: false,
+            quiet: false,
+            max_count: None,
+            only_matching: false,
+            group: 0,
+            replace: None,
+            colors: None,
+            binary_files: BinaryFiles::Binary,
+        }
+    }
+
+    #[test]
+    fn test_find_lines() {
+        let text = b"Lorem\nIpsum\r\nDOLOR";
+
+        // The pattern _or_ should match the one line, "Lorem"
+        let re1 = Regex::new("or").unwrap();
+        let matches = find_lines(Cursor::new(&text), &config(&re1, false, 0, 0), |_| Ok(()));
+        assert!(matches.is_ok());
+        assert_eq!(matches.unwrap(), 1);
+
+        // When inverted, the function should match the other two lines
+        let matches = find_lines(Cursor::new(&text), &config(&re1, true, 0, 0), |_| Ok(()));
+        assert!(matches.is_ok());
+        assert_eq!(matches.unwrap(), 2);
+
+        // This regex will be case-insensitive
+        let re2 = RegexBuilder::new("or")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+
+        // The two lines "Lorem" and "DOLOR" should match
+        let matches = find_lines(Cursor::new(&text), &config(&re2, false, 0, 0), |_| Ok(()));
+        assert!(matches.is_ok());
+        assert_eq!(matches.unwrap(), 2);
+
+        // When inverted, the one remaining line should match
+        let matches = find_lines(Cursor::new(&text), &config(&re2, true, 0, 0), |_| Ok(()));
+        assert!(matches.is_ok());
+        assert_eq!(matches.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_find_lines_context() {
+        let text = "a\nx\nb\nc\nd\ne\nx\nx\nf\n";
+        let re = Regex::new("x").unwrap();
+        let lines = |before, after| {
+            let mut lines = vec![];
+            let count = find_lines(
+                Cursor::new(text),
+                &config(&re, false, before, after),
+                |line| {
+                    lines.push(match line {
+                        Print::Match(line) => format!("{}:{}", line.number, lossy(line.text)),
+                        Print::Context(line) => format!("{}-{}", line.number, lossy(line.text)),
+                        Print::Separator => "--".to_string(),
+                        Print::Binary => "binary".to_string(),
+                    });
+                    Ok(())
+                },
+            );
+            assert_eq!(count.unwrap(), 3);
+            lines
+        };
+        assert_eq!(lines(0, 0), ["2:x", "7:x", "8:x"]);
+        assert_eq!(lines(1, 0), ["--", "1-a", "2:x", "--", "6-e", "7:x", "8:x"]);
+        assert_eq!(lines(0, 1), ["--", "2:x", "3-b", "--", "7:x", "8:x", "9-f"]);
+        // 間の行が前後の文脈で埋まる場合は、区切りを入れない
+        assert_eq!(
+            lines(2, 2),
+            [
+                "--", "1-a", "2:x", "3-b", "4-c", "5-d", "6-e", "7:x", "8:x", "9-f"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_lines_max_count() {
+        let text = "x\na\nx\nb\nc\nx\n";
+        let re = Regex::new("x").unwrap();
+        let mut config = config(&re, false, 0, 1);
+        config.max_count = Some(2);
+        let mut file = Cursor::new(text);
+        let mut lines = vec![];
+        let count = find_lines(&mut file, &config, |print| {
+            if let Print::Match(line) | Print::Context(line) = print {
+                lines.push(line.number);
+            }
+            Ok(())
+        });
+        assert_eq!(count.unwrap(), 2);
+        assert_eq!(lines, [1, 2, 3, 4]);
+        // 後ろの文脈を出力したら、残りは読まない
+        assert_eq!(file.position(), 8);
+
+        config.max_count = None;
+        config.quiet = true;
+        let mut file = Cursor::new(text);
+        let count = find_lines(&mut file, &config, |_| Ok(()));
+        assert_eq!(count.unwrap(), 1);
+        assert_eq!(file.position(), 2);
+    }
+
+    #[test]
+    fn test_find_lines_binary() {
+        let text = b"x1\n\xffx2\n\0a\nx3\nx4\n";
+        let re = Regex::new("x").unwrap();
+        let mut config = config(&re, false, 1, 0);
+        let find = |config: &Config| {
+            let mut lines = vec![];
+            let count = find_lines(Cursor::new(text), config, |print| {
+                lines.push(match print {
+                    Print::Match(line) => lossy(line.text),
+                    Print::Context(line) => format!("-{}", lossy(line.text)),
+                    Print::Separator => "--".to_string(),
+                    Print::Binary => "binary".to_string(),
+                });
+                Ok(())
+            });
+            (count.unwrap(), lines)
+        };
+        // 先頭の部分に NUL があるため、最初の一致で読むのをやめる
+        assert_eq!(find(&config), (1, vec!["binary".to_string()]));
+
+        config.binary_files = BinaryFiles::Text;
+        let (count, lines) = find(&config);
+        assert_eq!(count, 4);
+        assert_eq!(lines[..3], ["--", "x1", "\u{fffd}x2"]);
+
+        config.binary_files = BinaryFiles::WithoutMatch;
+        assert_eq!(find(&config), (0, vec![]));
+
+        // 行を出力しない場合は、すべて数える
+        config.binary_files = BinaryFiles::Binary;
+        config.count = true;
+        assert_eq!(find(&config).0, 4);
+    }
+
+    #[test]
+    fn test_find_files() {
+        // Verify that the function finds a file known to exist
+        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
+
+        // The function should reject a directory without the recursive option
+        let files = find_files(&["./tests/inputs".to_string()], false);
+        assert_eq!(files.len(), 1);
+        if let Err(e) = &files[0] {
+            assert_eq!(e.to_string(), "./tests/inputs is a directory");
+        }
+
+        // Verify the function recurses to find four files in the directory
+        let res = find_files(&["./tests/inputs".to_string()], true);
+        let mut files: Vec<String> = res
+            .iter()
+            .map(|r| r.as_ref().unwrap().replace("\\", "/"))
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            files,
+            vec![
+                "./tests/inputs/bustle.txt",
+                "./tests/inputs/empty.txt",
+                "./tests/inputs/fox.txt",
+                "./tests/inputs/nobody.txt",
+            ]
+        );
+
+        // Generate a random string to represent a nonexistent file
+        let bad: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(7)
+            .map(char::from)
+            .collect();
+
+        // Verify that the function returns the bad file as an error
+        let files = find_files(&[bad], false);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_err());
+    }
+}
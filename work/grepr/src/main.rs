@@ -1,6 +1,12 @@
 fn main() {
-    if let Err(e) = grepr::get_args().and_then(grepr::run) {
-        eprintln!("{}", e);
-        std::process::exit(1);
+    match grepr::get_args().and_then(grepr::run) {
+        // grep と同様に、一致しない場合は 1、エラーがあった場合は 2 で終了する
+        Ok((_, true)) => std::process::exit(2),
+        Ok((true, false)) => {}
+        Ok((false, false)) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
     }
 }
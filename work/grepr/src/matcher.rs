@@ -0,0 +1,394 @@
+use aho_corasick::AhoCorasick;
+use regex::bytes::{Captures, Regex, RegexBuilder};
+use std::{io, ops::Range, str};
+
+type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub fixed_strings: bool,
+    pub insensitive: bool,
+    pub word: bool,
+    pub line: bool,
+}
+
+// -F の場合は、多数のパターンでも一度の走査で探せるように Aho-Corasick を使う。
+// どちらも UTF-8 として正しくないバイト列をそのまま探せる。
+// expand が false の場合は、置き換える文字列の $1 などを展開しない。
+// word は -w の場合に、一致した位置から長さを変えて試すための全体に一致させる正規表現
+#[derive(Debug)]
+pub enum Matcher {
+    Regex {
+        regex: Regex,
+        expand: bool,
+        word: Option<Regex>,
+    },
+    Literal {
+        automaton: AhoCorasick,
+        word: bool,
+        line: bool,
+    },
+}
+
+impl Matcher {
+    pub fn new(patterns: &[String], options: &Options) -> MyResult<Matcher> {
+        // Aho-Corasick は ASCII の大文字と小文字しか同一視できないため、
+        // それ以外を含むパターンはエスケープして正規表現で探す
+        let fold = options.insensitive && patterns.iter().any(|pattern| !pattern.is_ascii());
+        // パターンがない場合は、何にも一致しない
+        if options.fixed_strings && !fold || patterns.is_empty() {
+            let automaton = AhoCorasick::builder()
+                .ascii_case_insensitive(options.insensitive)
+                .build(patterns)?;
+            return Ok(Matcher::Literal {
+                automaton,
+                word: options.word,
+                line: options.line,
+            });
+        }
+        let joined = patterns
+            .iter()
+            .map(|pattern| {
+                if options.fixed_strings {
+                    regex::escape(pattern)
+                } else {
+                    format!("(?:{})", pattern)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("|");
+        let pattern = if options.line {
+            format!("^(?:{})$", joined)
+        } else {
+            joined
+        };
+        let build = |pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(options.insensitive)
+                .build()
+        };
+        let expand = !options.fixed_strings;
+        build(&pattern)
+            .and_then(|regex| {
+                let word = if options.word && !options.line {
+                    Some(build(&format!("^(?:{})$", pattern))?)
+                } else {
+                    None
+                };
+                Ok(Matcher::Regex {
+                    regex,
+                    expand,
+                    word,
+                })
+            })
+            .map_err(|_| {
+                // どのパターンが不正かを示す
+                let invalid = patterns.iter().find(|pattern| build(pattern).is_err());
+                format!("Invalid pattern \"{}\"", invalid.unwrap_or(&pattern)).into()
+            })
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        match self {
+            Matcher::Regex {
+                regex, word: None, ..
+            } => regex.is_match(text),
+            Matcher::Regex {
+                regex,
+                word: Some(whole),
+                ..
+            } => word_captures(regex, whole, text).next().is_some(),
+            Matcher::Literal { automaton, .. } => automaton
+                .find_overlapping_iter(text)
+                .any(|m| self.accepts(text, m.range())),
+        }
+    }
+
+    // キャプチャグループの数。-F の場合は一致した部分全体だけ
+    pub fn captures_len(&self) -> usize {
+        match self {
+            Matcher::Regex { regex, .. } => regex.captures_len(),
+            Matcher::Literal { .. } => 1,
+        }
+    }
+
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        match self {
+            Matcher::Regex { regex, .. } => regex.capture_names().position(|n| n == Some(name)),
+            Matcher::Literal { .. } => None,
+        }
+    }
+
+    // 一致した部分を重ならないように左から順に、グループの範囲と出力する文字列を f に渡す。
+    // -F の場合は、置き換える文字列をそのまま使う
+    pub fn each_match(
+        &self,
+        text: &[u8],
+        group: usize,
+        replace: Option<&str>,
+        mut f: impl FnMut(Range<usize>, &[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match self {
+            Matcher::Regex {
+                regex,
+                expand,
+                word,
+            } => {
+                let mut expanded = vec![];
+                // -w で長さを変えて探した場合、キャプチャの位置は start からの相対位置になる
+                let found: Box<dyn Iterator<Item = (usize, Captures)>> = match word {
+                    Some(whole) => Box::new(word_captures(regex, whole, text)),
+                    None => Box::new(regex.captures_iter(text).map(|caps| (0, caps))),
+                };
+                for (start, caps) in found {
+                    let Some(m) = caps.get(group) else {
+                        continue;
+                    };
+                    let value = match replace {
+                        Some(replace) if !expand => replace.as_bytes(),
+                        Some(replace) => {
+                            expanded.clear();
+                            caps.expand(replace.as_bytes(), &mut expanded);
+                            &expanded
+                        }
+                        None => m.as_bytes(),
+                    };
+                    f(start + m.start()..start + m.end(), value)?;
+                }
+            }
+            Matcher::Literal { automaton, .. } => {
+                // grep -o と同様に、同じ位置から始まる場合は長い方を選ぶ
+                let mut found: Vec<Range<usize>> = automaton
+                    .find_overlapping_iter(text)
+                    .map(|m| m.range())
+                    .filter(|range| self.accepts(text, range.clone()))
+                    .collect();
+                found.sort_by_key(|range| (range.start, usize::MAX - range.end));
+                let mut end = 0;
+                for range in found {
+                    if range.start < end {
+                        continue;
+                    }
+                    end = range.end;
+                    let value = replace.map_or(&text[range.clone()], str::as_bytes);
+                    f(range, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // -w と -x の条件。正規表現の場合は -x だけをパターンに含めてある
+    fn accepts(&self, text: &[u8], range: Range<usize>) -> bool {
+        match self {
+            Matcher::Regex { .. } => true,
+            Matcher::Literal { line: true, .. } => range == (0..text.len()),
+            Matcher::Literal { word: true, .. } => is_word_match(text, range),
+            Matcher::Literal { .. } => true,
+        }
+    }
+}
+
+// -w で、前後が単語の文字でない一致を左から順に返す。
+// grep と同様に、条件を満たさない場合は同じ位置から長さを変えて試し、それでもだめなら次の位置から探す
+fn word_captures<'t>(
+    regex: &Regex,
+    whole: &Regex,
+    text: &'t [u8],
+) -> impl Iterator<Item = (usize, Captures<'t>)> {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        while at <= text.len() {
+            let caps = regex.captures_at(text, at)?;
+            let m = caps.get(0).unwrap().range();
+            at = m.start + 1;
+            if is_word_before(text, m.start) {
+                continue;
+            }
+            if is_word_match(text, m.clone()) {
+                at = at.max(m.end);
+                return Some((0, caps));
+            }
+            // 単語の文字が続かない位置で終わるものを、長い方から探す
+            let found = (m.start..=text.len())
+                .rev()
+                .filter(|&end| end != m.end && !is_word_after(text, end))
+                .find_map(|end| whole.captures(&text[m.start..end]));
+            if let Some(caps) = found {
+                at = at.max(m.start + caps.get(0).unwrap().end());
+                return Some((m.start, caps));
+            }
+        }
+        None
+    })
+}
+
+// grep -w と同様に、一致した部分の前後がどちらも単語の文字でないかどうか
+fn is_word_match(text: &[u8], range: Range<usize>) -> bool {
+    !is_word_before(text, range.start) && !is_word_after(text, range.end)
+}
+
+// UTF-8 として正しくないバイトは単語の文字とみなさない
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_word_before(text: &[u8], i: usize) -> bool {
+    (i.saturating_sub(4)..i)
+        .rev()
+        .find_map(|start| str::from_utf8(&text[start..i]).ok())
+        .and_then(|s| s.chars().next_back())
+        .is_some_and(is_word)
+}
+
+fn is_word_after(text: &[u8], i: usize) -> bool {
+    (i + 1..=text.len().min(i + 4))
+        .find_map(|end| str::from_utf8(&text[i..end]).ok())
+        .and_then(|s| s.chars().next())
+        .is_some_and(is_word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Matcher, Options};
+
+    fn matcher(patterns: &[&str], options: Options) -> Matcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Matcher::new(&patterns, &options).unwrap()
+    }
+
+    fn found(matcher: &Matcher, text: &[u8]) -> Vec<String> {
+        let mut found = vec![];
+        matcher
+            .each_match(text, 0, None, |_, value| {
+                found.push(String::from_utf8_lossy(value).to_string());
+                Ok(())
+            })
+            .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_regex() {
+        let m = matcher(&["fo+", "ba(r)"], Options::default());
+        assert!(m.is_match(b"xfoo"));
+        assert!(m.is_match(b"bar"));
+        assert!(!m.is_match(b"baz"));
+        assert_eq!(m.captures_len(), 2);
+
+        let m = matcher(
+            &["foo", "a|ab"],
+            Options {
+                word: true,
+                ..Options::default()
+            },
+        );
+        assert!(m.is_match(b"a foo."));
+        assert!(m.is_match(b"ab c"));
+        assert!(!m.is_match(b"food abc"));
+
+        // grep -w と同様に、単語の文字でない文字で始まる場合も、前後が単語の文字でないことを求める
+        let m = matcher(
+            &["-bar", "o+"],
+            Options {
+                word: true,
+                ..Options::default()
+            },
+        );
+        assert!(!m.is_match(b"foo-bar"));
+        assert!(m.is_match(b"x -bar"));
+        assert_eq!(found(&m, b"fooo-bar food -bar oo"), ["-bar", "oo"]);
+
+        let m = matcher(
+            &["fo+"],
+            Options {
+                line: true,
+                insensitive: true,
+                ..Options::default()
+            },
+        );
+        assert!(m.is_match(b"FOO"));
+        assert!(!m.is_match(b"foo bar"));
+
+        let res = Matcher::new(&["ok".to_string(), "*bad".to_string()], &Options::default());
+        assert_eq!(res.unwrap_err().to_string(), "Invalid pattern \"*bad\"");
+    }
+
+    #[test]
+    fn test_literal() {
+        let fixed = || Options {
+            fixed_strings: true,
+            ..Options::default()
+        };
+        let m = matcher(&["a.b", "ab", "abc"], fixed());
+        assert!(m.is_match(b"xa.by"));
+        assert!(!m.is_match(b"axb"));
+        assert_eq!(found(&m, b"abcd a.b ab"), ["abc", "a.b", "ab"]);
+
+        // 長い方が単語の途中で終わる場合は、短い方を探す
+        let m = matcher(
+            &["10.0.0.1", "10.0.0.12"],
+            Options {
+                word: true,
+                ..fixed()
+            },
+        );
+        assert!(m.is_match(b"from 10.0.0.1 to"));
+        assert!(!m.is_match(b"from 10.0.0.123"));
+        assert_eq!(found(&m, b"10.0.0.12,10.0.0.1x"), ["10.0.0.12"]);
+
+        let m = matcher(
+            &["-bar"],
+            Options {
+                word: true,
+                ..fixed()
+            },
+        );
+        assert!(!m.is_match(b"foo-bar"));
+        assert!(m.is_match(b"x -bar"));
+
+        let m = matcher(
+            &["ab", "abc"],
+            Options {
+                line: true,
+                ..fixed()
+            },
+        );
+        assert!(m.is_match(b"ab"));
+        assert!(!m.is_match(b"abcd"));
+
+        let m = matcher(
+            &["Foo"],
+            Options {
+                insensitive: true,
+                ..fixed()
+            },
+        );
+        assert!(m.is_match(b"xFOOx"));
+
+        // ASCII 以外の大文字と小文字も同一視する
+        let m = matcher(
+            &["äbc", "a.b"],
+            Options {
+                insensitive: true,
+                ..fixed()
+            },
+        );
+        assert!(m.is_match("ÄBC".as_bytes()));
+        assert!(m.is_match(b"A.B"));
+        assert!(!m.is_match(b"axb"));
+
+        // UTF-8 として正しくないバイトは単語の区切りになる
+        let m = matcher(
+            &["ab"],
+            Options {
+                word: true,
+                ..fixed()
+            },
+        );
+        assert_eq!(found(&m, b"\xffab\xfe \xc3\xa9ab"), ["ab"]);
+
+        let m = matcher(&[], Options::default());
+        assert!(!m.is_match(b""));
+    }
+}
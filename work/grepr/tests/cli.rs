@@ -1,6 +1,6 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
-use rand::{distributions::Alphanumeric, Rng};
+use rand::{Rng, distributions::Alphanumeric};
 use std::{fs, path::Path};
 use sys_info::os_type;
 
@@ -42,7 +42,7 @@ fn dies_no_args() -> TestResult {
 #[test]
 fn dies_bad_pattern() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["*foo", FOX])
+        .args(["*foo", FOX])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
@@ -55,7 +55,7 @@ fn warns_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&["foo", &bad])
+        .args(["foo", &bad])
         .assert()
         .stderr(predicate::str::is_match(expected)?);
     Ok(())
@@ -64,15 +64,13 @@ fn warns_bad_file() -> TestResult {
 // --------------------------------------------------
 fn run(args: &[&str], expected_file: &str) -> TestResult {
     let windows_file = format!("{}.windows", expected_file);
-    let expected_file = if os_type().unwrap() == "Windows"
-        && Path::new(&windows_file).is_file()
-    {
+    let expected_file = if os_type().unwrap() == "Windows" && Path::new(&windows_file).is_file() {
         &windows_file
     } else {
         expected_file
     };
 
-    let expected = fs::read_to_string(&expected_file)?;
+    let expected = fs::read_to_string(expected_file)?;
 
     Command::cargo_bin(PRG)?
         .args(args)
@@ -234,7 +232,7 @@ fn warns_dir_not_recursive() -> TestResult {
     let stdout = "tests/inputs/fox.txt:\
         The quick brown fox jumps over the lazy dog.";
     Command::cargo_bin(PRG)?
-        .args(&["fox", INPUTS_DIR, FOX])
+        .args(["fox", INPUTS_DIR, FOX])
         .assert()
         .stderr(predicate::str::contains("tests/inputs is a directory"))
         .stdout(predicate::str::contains(stdout));
@@ -245,8 +243,7 @@ fn warns_dir_not_recursive() -> TestResult {
 #[test]
 fn stdin() -> TestResult {
     let input = fs::read_to_string(BUSTLE)?;
-    let expected =
-        fs::read_to_string("tests/expected/bustle.txt.the.capitalized")?;
+    let expected = fs::read_to_string("tests/expected/bustle.txt.the.capitalized")?;
 
     Command::cargo_bin(PRG)?
         .arg("The")
@@ -266,14 +263,404 @@ fn stdin_insensitive_count() -> TestResult {
         input += &fs::read_to_string(file)?;
     }
 
-    let expected_file =
-        "tests/expected/the.recursive.insensitive.count.stdin";
+    let expected_file = "tests/expected/the.recursive.insensitive.count.stdin";
     let expected = fs::read_to_string(expected_file)?;
 
     Command::cargo_bin(PRG)?
-        .args(&["-ci", "the", "-"])
+        .args(["-ci", "the", "-"])
         .write_stdin(input)
         .assert()
         .stdout(expected);
     Ok(())
 }
+
+// --------------------------------------------------
+fn run_stdin(args: &[&str], input: &str, expected: &str) -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(expected.to_string());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context() -> TestResult {
+    let input = "a\nx\nb\nc\nd\ne\nx\nf\n";
+    run_stdin(&["-A", "1", "x"], input, "x\nb\n--\nx\nf\n")?;
+    run_stdin(&["-B", "1", "x"], input, "a\nx\n--\ne\nx\n")?;
+    run_stdin(&["-C", "2", "x"], input, "a\nx\nb\nc\nd\ne\nx\nf\n")?;
+    run_stdin(
+        &["-C", "3", "-A", "0", "x"],
+        input,
+        "a\nx\n--\nc\nd\ne\nx\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_multiple_files() -> TestResult {
+    let expected = "tests/inputs/bustle.txt-Is solemnest of industries\n\
+        tests/inputs/bustle.txt:Enacted upon earth,—\n\
+        --\n\
+        tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n";
+    Command::cargo_bin(PRG)?
+        .args(["-B1", "earth|lazy", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_and_byte_offset() -> TestResult {
+    let input = "ab\ncd\nab\n";
+    run_stdin(&["-n", "ab"], input, "1:ab\n3:ab\n")?;
+    run_stdin(&["-b", "ab"], input, "0:ab\n6:ab\n")?;
+    run_stdin(
+        &["-nb", "-B1", "ab", "-"],
+        input,
+        "1:0:ab\n2-3-cd\n3:6:ab\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn filename_control() -> TestResult {
+    run_stdin(&["-H", "ab"], "ab\n", "-:ab\n")?;
+    run_stdin(&["-Hn", "--label", "in", "ab"], "ab\n", "in:1:ab\n")?;
+    run_stdin(&["-c", "-H", "-h", "ab", "-"], "ab\n", "1\n")?;
+    Command::cargo_bin(PRG)?
+        .args(["-hc", "The", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("3\n1\n");
+    Command::cargo_bin(PRG)?
+        .args(["-Hn", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/fox.txt:1:The quick brown fox jumps over the lazy dog.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_and_without_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "The", BUSTLE, EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt\ntests/inputs/fox.txt\n");
+    Command::cargo_bin(PRG)?
+        .args(["-L", "The", BUSTLE, EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/empty.txt\n");
+    Command::cargo_bin(PRG)?
+        .args(["-L", "The", BUSTLE, FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["-q", "nothing", BUSTLE, FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn missing_file_status() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["fox", &bad])
+        .assert()
+        .code(2)
+        .stdout("");
+    // 一致する行があっても、読めないファイルがあれば 2 で終了する
+    Command::cargo_bin(PRG)?
+        .args(["fox", FOX, &bad])
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains("lazy dog"));
+    // -q で一致した場合は、エラーがあっても 0 で終了する
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", &bad, FOX])
+        .assert()
+        .success();
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count() -> TestResult {
+    let input = "x1\na\nx2\nb\nx3\n";
+    run_stdin(&["-m", "2", "x"], input, "x1\nx2\n")?;
+    run_stdin(&["-m2", "-A1", "x"], input, "x1\na\nx2\nb\n")?;
+    run_stdin(&["-cm", "2", "x"], input, "2\n")?;
+    Command::cargo_bin(PRG)?
+        .args(["-m", "0", "x"])
+        .write_stdin(input)
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching() -> TestResult {
+    let input = "id=12 url=http://a/b id=7\nnone\nid=x9\n";
+    run_stdin(&["-o", r"id=\d+"], input, "id=12\nid=7\n")?;
+    run_stdin(&["-ob", r"id=\d+"], input, "0:id=12\n21:id=7\n")?;
+    run_stdin(&["-o", "--group", "1", r"id=(\d+)"], input, "12\n7\n")?;
+    run_stdin(
+        &["-on", "--group", "id", r"id=(?P<id>\d+)|(none)"],
+        input,
+        "1:12\n1:7\n",
+    )?;
+    run_stdin(&["-o", "-C1", "none"], input, "none\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> TestResult {
+    let input = "id=12 url=http://a/b id=7\nnone\n";
+    run_stdin(
+        &["--replace", "<$1>", r"id=(\d+)"],
+        input,
+        "<12> url=http://a/b <7>\n",
+    )?;
+    run_stdin(
+        &["-o", "--replace", "ID ${n}", r"id=(?P<n>\d+)"],
+        input,
+        "ID 12\nID 7\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_patterns() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", "-e", "bustle", FOX, BUSTLE])
+        .assert()
+        .success()
+        .stdout(
+            "tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n\
+            tests/inputs/bustle.txt:The bustle in a house\n",
+        );
+    // -e がある場合は、最初の引数もファイルになる
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\n");
+    run_stdin(&["-e", "a.c", "-e", "d"], "abc\nd\ne\n", "abc\nd\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn pattern_file_fixed_strings() -> TestResult {
+    let input = "from 10.0.0.1 ok\nfrom 10.0.0.12\nfrom 10a0a0a1\nto 192.168.1.7\n";
+    run_stdin(
+        &["-f", "tests/patterns/ips.txt"],
+        input,
+        "from 10.0.0.1 ok\nfrom 10.0.0.12\nfrom 10a0a0a1\nto 192.168.1.7\n",
+    )?;
+    run_stdin(
+        &["-Ff", "tests/patterns/ips.txt"],
+        input,
+        "from 10.0.0.1 ok\nfrom 10.0.0.12\nto 192.168.1.7\n",
+    )?;
+    run_stdin(
+        &["-Fwn", "-f", "tests/patterns/ips.txt"],
+        input,
+        "1:from 10.0.0.1 ok\n4:to 192.168.1.7\n",
+    )?;
+    run_stdin(
+        &["-Fo", "-f", "tests/patterns/ips.txt"],
+        input,
+        "10.0.0.1\n10.0.0.1\n192.168.1.7\n",
+    )?;
+    Command::cargo_bin(PRG)?
+        .args(["-f", "/dev/null", FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings_insensitive() -> TestResult {
+    let input = "ÄBC\nabc\nA.C $1\n";
+    run_stdin(&["-i", "äbc"], input, "ÄBC\n")?;
+    run_stdin(&["-Fi", "äbc"], input, "ÄBC\n")?;
+    run_stdin(&["-Fi", "-e", "äbc", "-e", "a.c"], input, "ÄBC\nA.C $1\n")?;
+    // 正規表現で探す場合も、置き換える文字列はそのまま使う
+    run_stdin(
+        &["-Fi", "--replace", "<$1>", "-e", "ä", "-e", "$1"],
+        input,
+        "<$1>BC\nA.C <$1>\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn word_and_line() -> TestResult {
+    let input = "foo\nfood\na foo.\nFOO\n";
+    run_stdin(&["-w", "foo"], input, "foo\na foo.\n")?;
+    run_stdin(&["-x", "foo"], input, "foo\n")?;
+    run_stdin(&["-xi", "foo|food"], input, "foo\nfood\nFOO\n")?;
+    run_stdin(&["-Fxi", "foo"], input, "foo\nFOO\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn color() -> TestResult {
+    let input = "the fox\nno\n";
+    run_stdin(
+        &["--color=always", "-n", "fox"],
+        input,
+        "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0mthe \x1b[1;31mfox\x1b[0m\n",
+    )?;
+    run_stdin(
+        &["--color=always", "-H", "--label", "in", "-o", "o"],
+        input,
+        "\x1b[35min\x1b[0m\x1b[36m:\x1b[0m\x1b[1;31mo\x1b[0m\n"
+            .repeat(2)
+            .as_str(),
+    )?;
+    // 出力が端末でない場合は色を付けない
+    run_stdin(&["--color", "fox"], input, "the fox\n")?;
+    run_stdin(&["--color=never", "fox"], input, "the fox\n")?;
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "fox"])
+        .env("GREP_COLORS", "ms=04;32:bad")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("the \x1b[4;32mfox\x1b[0m\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files() -> TestResult {
+    // UTF-8 として正しくないバイトがあっても、そのまま出力する
+    Command::cargo_bin(PRG)?
+        .args(["-n", "caf"])
+        .write_stdin(b"ok\ncaf\xe9\n".to_vec())
+        .assert()
+        .success()
+        .stdout(b"2:caf\xe9\n".to_vec());
+
+    let input = "a x\n\0b\nx\n";
+    run_stdin(&["x"], input, "Binary file - matches\n")?;
+    run_stdin(&["-a", "x"], input, "a x\nx\n")?;
+    run_stdin(&["--binary-files=text", "-c", "x"], input, "2\n")?;
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files=without-match", "x"])
+        .write_stdin(input)
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn run_closed_stdout(
+    args: &[&str],
+    input: &[u8],
+) -> Result<std::process::Output, Box<dyn std::error::Error>> {
+    use std::{io::Write, process::Stdio};
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // 出力を読む前にパイプを閉じる。grepr が先に終了した場合の書き込みエラーは無視する
+    drop(child.stdout.take());
+    let _ = child.stdin.take().unwrap().write_all(input);
+    Ok(child.wait_with_output()?)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn broken_pipe() -> TestResult {
+    // grep と同様に、出力先が閉じられても一致した結果の終了ステータスで終了する
+    let input = "1\n".repeat(100_000);
+    for args in [
+        &["1"][..],
+        &["-n", "-C", "1", "1"],
+        &["-l", "1"],
+        &["-c", "1"],
+    ] {
+        let output = run_closed_stdout(args, input.as_bytes())?;
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(String::from_utf8(output.stderr)?, "");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m", "x", "foo", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid max count \"x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_group() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-o", "--group", "2", "(a)", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid group \"2\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_pattern_list() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-e", "ok", "-e", "*foo", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-A", "x", "foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid context length \"x\""));
+    Ok(())
+}